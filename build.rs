@@ -9,6 +9,12 @@ fn main() {
         .cpp(true)
         .warnings(false);
 
+    // xatlas prints through a variadic function, which can't be written in Rust.
+    if env::var_os("CARGO_FEATURE_LOG").is_some() || env::var_os("CARGO_FEATURE_TRACING").is_some()
+    {
+        build.file("src/print.cpp");
+    }
+
     if let Ok(crt) = env::var("XATLAS_MSVC_CRT") {
         match crt.as_str() {
             "dynamic" => build.static_crt(false),
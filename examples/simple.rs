@@ -21,7 +21,9 @@ fn main() {
         true
     });
     atlas.add_mesh(&mesh).unwrap();
-    atlas.generate(&Default::default(), &Default::default());
+    atlas
+        .generate(&Default::default(), &Default::default())
+        .unwrap();
     let meshes = atlas.meshes();
 
     dbg!(meshes);
@@ -0,0 +1,446 @@
+//! Interleaved vertex layouts, vertex formats other than f32, and rebuilding vertex attributes
+//! for output meshes through `Vertex::xref`.
+
+use crate::{AddMeshError, Mesh, MeshData, MeshDecl, VertexStream};
+use std::convert::TryFrom;
+
+/// An attribute of an interleaved vertex buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Attr {
+    /// Byte offset of the attribute from the start of a vertex.
+    pub offset: usize,
+    /// Format of each component. The number of components follows from the attribute, e.g.
+    /// 2 for UVs.
+    pub format: AttrFormat,
+}
+
+/// Format of a single component of an `Attr`, in native byte order. xatlas only reads f32,
+/// other formats are converted on the Rust side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttrFormat {
+    F32,
+    /// IEEE 754 half precision float.
+    F16,
+    /// Signed 16-bit integer mapped to -1 to 1.
+    Snorm16,
+    /// Unsigned 16-bit integer mapped to 0 to 1.
+    Unorm16,
+}
+
+/// Transform applied to every decoded component of `MeshData::Encoded`, `value * scale + offset`,
+/// e.g. to map normalized positions back to the bounds they were quantized in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Dequantize {
+    pub scale: [f32; 3],
+    pub offset: [f32; 3],
+}
+
+impl Attr {
+    pub fn new(offset: usize, format: AttrFormat) -> Self {
+        Attr { offset, format }
+    }
+
+    /// Number of bytes `components` components of this attribute take up.
+    pub fn size(&self, components: usize) -> usize {
+        self.format.size() * components
+    }
+}
+
+impl AttrFormat {
+    /// Size of a component in bytes.
+    pub fn size(self) -> usize {
+        match self {
+            AttrFormat::F32 => 4,
+            AttrFormat::F16 | AttrFormat::Snorm16 | AttrFormat::Unorm16 => 2,
+        }
+    }
+
+    pub(crate) fn read(self, bytes: &[u8]) -> f32 {
+        match self {
+            AttrFormat::F32 => f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            AttrFormat::F16 => f16_to_f32(u16::from_ne_bytes([bytes[0], bytes[1]])),
+            // Both -32768 and -32767 map to -1.
+            AttrFormat::Snorm16 => {
+                (i16::from_ne_bytes([bytes[0], bytes[1]]) as f32 / 32767.0).max(-1.0)
+            }
+            AttrFormat::Unorm16 => u16::from_ne_bytes([bytes[0], bytes[1]]) as f32 / 65535.0,
+        }
+    }
+
+    /// Writes `value`, clamping it to the range of normalized formats.
+    pub(crate) fn write(self, value: f32, out: &mut [u8]) {
+        match self {
+            AttrFormat::F32 => out[..4].copy_from_slice(&value.to_ne_bytes()),
+            AttrFormat::F16 => out[..2].copy_from_slice(&f32_to_f16(value).to_ne_bytes()),
+            AttrFormat::Snorm16 => {
+                let value = (value.clamp(-1.0, 1.0) * 32767.0).round() as i16;
+                out[..2].copy_from_slice(&value.to_ne_bytes());
+            }
+            AttrFormat::Unorm16 => {
+                let value = (value.clamp(0.0, 1.0) * 65535.0).round() as u16;
+                out[..2].copy_from_slice(&value.to_ne_bytes());
+            }
+        }
+    }
+}
+
+impl Dequantize {
+    pub const IDENTITY: Dequantize = Dequantize {
+        scale: [1.0; 3],
+        offset: [0.0; 3],
+    };
+
+    /// Maps the 0 to 1 range of `Unorm16` components to the box from `min` to `max`.
+    pub fn from_bounds(min: [f32; 3], max: [f32; 3]) -> Self {
+        Dequantize {
+            scale: [max[0] - min[0], max[1] - min[1], max[2] - min[2]],
+            offset: min,
+        }
+    }
+
+    pub(crate) fn apply(&self, component: usize, value: f32) -> f32 {
+        value * self.scale[component] + self.offset[component]
+    }
+}
+
+impl Default for Dequantize {
+    fn default() -> Self {
+        Dequantize::IDENTITY
+    }
+}
+
+/// Describes a buffer of interleaved vertices, so a `MeshDecl` can read positions, normals and
+/// UVs from it without copying them into separate buffers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VertexLayout {
+    /// Size of a vertex in bytes.
+    pub stride: u32,
+    pub position: Attr,
+    pub normal: Option<Attr>,
+    /// Input UVs, a hint for the chart generator.
+    pub uv: Option<Attr>,
+}
+
+impl VertexLayout {
+    /// Creates a `MeshDecl` that reads the attributes of the layout from `data`, which has to
+    /// be a whole number of vertices long. Attributes in formats other than f32 become
+    /// `MeshData::Encoded` without a dequantization transform. f32 attributes are passed to
+    /// xatlas without copying when `data`, the offset and the stride are 4 byte aligned, and
+    /// are copied otherwise. Everything else is left at its
+    /// default, e.g. the index data has to be set afterwards.
+    pub fn mesh_decl<'a>(&self, data: &'a [u8]) -> Result<MeshDecl<'a>, AddMeshError> {
+        self.validate(data)?;
+        let stream = |attr: Attr| {
+            let data = data.get(attr.offset..).unwrap_or(&[]);
+            match attr.format {
+                AttrFormat::F32 => MeshData::WithStride {
+                    data,
+                    stride: self.stride,
+                },
+                format => MeshData::Encoded {
+                    data,
+                    stride: self.stride,
+                    format,
+                    dequantize: Dequantize::IDENTITY,
+                },
+            }
+        };
+        Ok(MeshDecl {
+            vertex_position_data: stream(self.position),
+            vertex_normal_data: self.normal.map(stream),
+            vertex_uv_data: self.uv.map(stream),
+            ..MeshDecl::default()
+        })
+    }
+
+    /// Number of vertices in `data`, if it is a whole number of vertices long.
+    pub fn vertex_count(&self, data: &[u8]) -> Option<u32> {
+        let stride = self.stride as usize;
+        if stride == 0 || !data.len().is_multiple_of(stride) {
+            None
+        } else {
+            u32::try_from(data.len() / stride).ok()
+        }
+    }
+
+    fn attributes(&self) -> [Option<(VertexStream, Attr)>; 3] {
+        [
+            Some((VertexStream::Position, self.position)),
+            self.normal.map(|attr| (VertexStream::Normal, attr)),
+            self.uv.map(|attr| (VertexStream::Uv, attr)),
+        ]
+    }
+
+    fn validate(&self, data: &[u8]) -> Result<(), AddMeshError> {
+        for &(stream, attr) in self.attributes().iter().flatten() {
+            let end = attr.offset + attr.size(stream.components() as usize);
+            if end > self.stride as usize {
+                return Err(AddMeshError::AttributeOutsideStride {
+                    stream,
+                    end,
+                    stride: self.stride,
+                });
+            }
+        }
+        match self.vertex_count(data) {
+            Some(_) => Ok(()),
+            None => Err(AddMeshError::BufferLengthMismatch {
+                len: data.len(),
+                stride: self.stride,
+            }),
+        }
+    }
+}
+
+pub(crate) fn encode_uvs(mesh: &Mesh<'_>, format: AttrFormat, uv_scale: [f32; 2]) -> Vec<u8> {
+    let size = format.size();
+    let mut output = vec![0; mesh.vertex_array.len() * size * 2];
+    for (vertex, element) in mesh
+        .vertex_array
+        .iter()
+        .zip(output.chunks_exact_mut(size * 2))
+    {
+        format.write(vertex.uv[0] * uv_scale[0], &mut element[..size]);
+        format.write(vertex.uv[1] * uv_scale[1], &mut element[size..]);
+    }
+    output
+}
+
+pub(crate) fn remap<T: Copy>(mesh: &Mesh<'_>, input: &[T]) -> Vec<T> {
+    mesh.vertex_array
+        .iter()
+        .map(|vertex| input[vertex.xref as usize])
+        .collect()
+}
+
+pub(crate) fn remap_strided(mesh: &Mesh<'_>, input: &[u8], stride: usize, size: usize) -> Vec<u8> {
+    let mut output = Vec::with_capacity(mesh.vertex_array.len() * size);
+    for vertex in &mesh.vertex_array {
+        let start = vertex.xref as usize * stride;
+        output.extend_from_slice(&input[start..start + size]);
+    }
+    output
+}
+
+pub(crate) fn remap_interleaved(
+    mesh: &Mesh<'_>,
+    input: &[u8],
+    layout: &VertexLayout,
+    uv_scale: [f32; 2],
+) -> Vec<u8> {
+    let stride = layout.stride as usize;
+    let uv = layout
+        .uv
+        .expect("the vertex layout has no UV attribute to write the atlas UVs to");
+    assert!(
+        uv.offset + uv.size(2) <= stride,
+        "the UV attribute doesn't fit in the vertex stride"
+    );
+    let mut output = remap_strided(mesh, input, stride, stride);
+    for (vertex, element) in mesh
+        .vertex_array
+        .iter()
+        .zip(output.chunks_exact_mut(stride))
+    {
+        let size = uv.format.size();
+        for (axis, scale) in uv_scale.iter().enumerate() {
+            let start = uv.offset + axis * size;
+            uv.format
+                .write(vertex.uv[axis] * scale, &mut element[start..start + size]);
+        }
+    }
+    output
+}
+
+fn f16_to_f32(half: u16) -> f32 {
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = (half >> 10) & 0x1F;
+    let mantissa = (half & 0x3FF) as u32;
+    match exponent {
+        0 => sign * mantissa as f32 * 2.0f32.powi(-24),
+        0x1F => f32::from_bits(((half as u32 & 0x8000) << 16) | 0x7F80_0000 | (mantissa << 13)),
+        _ => f32::from_bits(
+            ((half as u32 & 0x8000) << 16) | ((exponent as u32 + 112) << 23) | (mantissa << 13),
+        ),
+    }
+}
+
+/// Converts to the nearest half float, ties to even. Values too large for a half float become
+/// infinite.
+fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xFF) as i32;
+    let mantissa = bits & 0x7F_FFFF;
+    if exponent == 0xFF {
+        let nan = if mantissa != 0 { 0x200 } else { 0 };
+        return sign | 0x7C00 | nan;
+    }
+
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1F {
+        return sign | 0x7C00;
+    }
+    let (half, shift, mantissa) = if exponent <= 0 {
+        if exponent < -10 {
+            return sign;
+        }
+        // Subnormal, the implicit leading bit becomes part of the mantissa.
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - exponent) as u32;
+        (mantissa >> shift, shift, mantissa)
+    } else {
+        (((exponent as u32) << 10) | (mantissa >> 13), 13, mantissa)
+    };
+    let remainder = mantissa & ((1 << shift) - 1);
+    let halfway = 1 << (shift - 1);
+    // A carry out of the mantissa correctly increments the exponent, up to infinity.
+    let rounded = half + (remainder > halfway || (remainder == halfway && half & 1 == 1)) as u32;
+    sign | rounded as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Vertex;
+    use std::borrow::Cow;
+
+    /// Output vertices referencing input vertices through `xrefs`, output vertex `i` has the UV
+    /// `[i, 10 + i]`.
+    fn mesh(xrefs: &[u32]) -> Mesh<'static> {
+        Mesh {
+            index_array: Cow::Owned(Vec::new()),
+            chart_array: Vec::new(),
+            vertex_array: xrefs
+                .iter()
+                .enumerate()
+                .map(|(index, &xref)| Vertex {
+                    atlas_index: 0,
+                    chart_index: 0,
+                    uv: [index as f32, 10.0 + index as f32],
+                    xref,
+                })
+                .collect(),
+        }
+    }
+
+    /// Input vertex 2 is split into two output vertices.
+    const XREFS: [u32; 4] = [2, 0, 2, 1];
+
+    #[test]
+    fn remap_copies_the_element_of_every_xref() {
+        let colors = [[1u8, 0, 0], [0, 1, 0], [0, 0, 1]];
+        assert_eq!(
+            remap(&mesh(&XREFS), &colors),
+            [[0, 0, 1], [1, 0, 0], [0, 0, 1], [0, 1, 0]]
+        );
+    }
+
+    #[test]
+    fn remap_strided_skips_the_padding_between_elements() {
+        // Two bytes of attribute followed by two bytes of padding per vertex.
+        let input = [10u8, 11, 0xFF, 0xFF, 20, 21, 0xFF, 0xFF, 30, 31, 0xFF, 0xFF];
+        assert_eq!(
+            remap_strided(&mesh(&XREFS), &input, 4, 2),
+            [30, 31, 10, 11, 30, 31, 20, 21]
+        );
+    }
+
+    #[test]
+    fn remap_interleaved_keeps_other_attributes_and_writes_the_atlas_uvs() {
+        // A position followed by a Unorm16 UV and a byte of padding.
+        let layout = VertexLayout {
+            stride: 17,
+            position: Attr::new(0, AttrFormat::F32),
+            normal: None,
+            uv: Some(Attr::new(12, AttrFormat::Unorm16)),
+        };
+        let mut input = Vec::new();
+        for vertex in 0..3u8 {
+            for component in 0..3 {
+                input.extend_from_slice(&f32::from(vertex * 3 + component).to_ne_bytes());
+            }
+            input.extend_from_slice(&[0xAA; 4]);
+            input.push(vertex);
+        }
+
+        let mesh = mesh(&XREFS);
+        let output = remap_interleaved(&mesh, &input, &layout, [1.0 / 4.0, 1.0 / 16.0]);
+        assert_eq!(output.len(), XREFS.len() * 17);
+        for (vertex, element) in mesh.vertex_array.iter().zip(output.chunks_exact(17)) {
+            let xref = vertex.xref as usize;
+            assert_eq!(element[..12], input[xref * 17..xref * 17 + 12]);
+            assert_eq!(element[16], xref as u8);
+            let uv = [
+                AttrFormat::Unorm16.read(&element[12..14]),
+                AttrFormat::Unorm16.read(&element[14..16]),
+            ];
+            assert_eq!(
+                uv,
+                [vertex.uv[0] / 4.0, vertex.uv[1] / 16.0]
+                    .map(|c| { (c * 65535.0).round() / 65535.0 })
+            );
+        }
+    }
+
+    #[test]
+    fn half_floats_round_trip() {
+        for half in 0..=u16::MAX {
+            let value = f16_to_f32(half);
+            if value.is_nan() {
+                continue;
+            }
+            assert_eq!(f32_to_f16(value), half, "{half:#06x} decoded as {value}");
+        }
+    }
+
+    #[test]
+    fn half_float_zeros_keep_their_sign() {
+        assert_eq!(f32_to_f16(0.0), 0x0000);
+        assert_eq!(f32_to_f16(-0.0), 0x8000);
+        assert_eq!(f16_to_f32(0x0000).to_bits(), 0.0f32.to_bits());
+        assert_eq!(f16_to_f32(0x8000).to_bits(), (-0.0f32).to_bits());
+    }
+
+    #[test]
+    fn half_float_subnormals() {
+        let smallest = 2.0f32.powi(-24);
+        assert_eq!(f16_to_f32(0x0001), smallest);
+        assert_eq!(f32_to_f16(smallest), 0x0001);
+        assert_eq!(f32_to_f16(-smallest), 0x8001);
+        // Halfway between 0 and the smallest subnormal rounds to the even zero.
+        assert_eq!(f32_to_f16(smallest * 0.5), 0x0000);
+        assert_eq!(f32_to_f16(smallest * 0.75), 0x0001);
+        assert_eq!(f16_to_f32(0x03FF), 1023.0 * smallest);
+    }
+
+    #[test]
+    fn half_float_rounding_ties_to_even() {
+        let ulp = 2.0f32.powi(-10);
+        assert_eq!(f32_to_f16(1.0 + ulp * 0.5), 0x3C00);
+        assert_eq!(f32_to_f16(1.0 + ulp * 1.5), 0x3C02);
+        assert_eq!(f32_to_f16(1.0 + ulp * 0.5001), 0x3C01);
+    }
+
+    #[test]
+    fn half_float_overflow() {
+        assert_eq!(f32_to_f16(65504.0), 0x7BFF);
+        assert_eq!(f16_to_f32(0x7BFF), 65504.0);
+        assert_eq!(f32_to_f16(65519.0), 0x7BFF);
+        // Halfway between the largest half float and the next power of two rounds up to it.
+        assert_eq!(f32_to_f16(65520.0), 0x7C00);
+        assert_eq!(f32_to_f16(-1e10), 0xFC00);
+        assert_eq!(f16_to_f32(0x7C00), f32::INFINITY);
+        assert_eq!(f32_to_f16(f32::NEG_INFINITY), 0xFC00);
+    }
+
+    #[test]
+    fn half_float_nan() {
+        let half = f32_to_f16(f32::NAN);
+        assert_eq!(half & 0x7C00, 0x7C00);
+        assert_ne!(half & 0x03FF, 0);
+        assert!(f16_to_f32(half).is_nan());
+        assert!(f16_to_f32(0x7E00).is_nan());
+        assert!(f16_to_f32(0xFC01).is_nan());
+    }
+}
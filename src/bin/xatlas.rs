@@ -0,0 +1,270 @@
+//! Unwraps mesh files from the command line.
+//!
+//! Every input is unwrapped into its own atlas. OBJ, PLY and STL inputs are written back as OBJ,
+//! glTF and GLB inputs as GLB, next to a preview image of every atlas page.
+
+use clap::{Parser, ValueEnum};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use xatlas_rs::gltf::GltfScene;
+use xatlas_rs::obj::{self, ObjGroups, ObjMesh, ObjWriteOptions};
+use xatlas_rs::ply::PlyMesh;
+use xatlas_rs::stl::StlMesh;
+use xatlas_rs::{ChartOptions, MeshDecl, PackOptions, Xatlas};
+
+#[derive(Parser)]
+#[command(version, about = "Generates lightmap UVs for meshes with xatlas")]
+struct Args {
+    /// Mesh files to unwrap: .obj, .ply, .stl, .gltf or .glb.
+    #[arg(required = true)]
+    inputs: Vec<PathBuf>,
+
+    /// Directory for the output files, created if missing. Defaults to the directory of every
+    /// input.
+    #[arg(short, long)]
+    output_dir: Option<PathBuf>,
+
+    /// Don't write the atlas preview images.
+    #[arg(long)]
+    no_image: bool,
+
+    /// How the faces of OBJ output are grouped.
+    #[arg(long, value_enum, default_value_t = Groups::None)]
+    obj_groups: Groups,
+
+    #[command(flatten)]
+    chart: ChartArgs,
+
+    #[command(flatten)]
+    pack: PackArgs,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Groups {
+    None,
+    Atlas,
+    Chart,
+}
+
+#[derive(clap::Args)]
+#[command(next_help_heading = "Chart options")]
+struct ChartArgs {
+    /// Don't grow charts to be larger than this. 0 means no limit.
+    #[arg(long, default_value_t = ChartOptions::default().max_chart_area)]
+    max_chart_area: f32,
+    /// Don't grow charts to have a longer boundary than this. 0 means no limit.
+    #[arg(long, default_value_t = ChartOptions::default().max_boundary_length)]
+    max_boundary_length: f32,
+    /// Cost of the angle between a face and the average chart normal.
+    #[arg(long, default_value_t = ChartOptions::default().normal_deviation_weight)]
+    normal_deviation_weight: f32,
+    #[arg(long, default_value_t = ChartOptions::default().roundness_weight)]
+    roundness_weight: f32,
+    #[arg(long, default_value_t = ChartOptions::default().straightness_weight)]
+    straightness_weight: f32,
+    /// If > 1000, normal seams are fully respected.
+    #[arg(long, default_value_t = ChartOptions::default().normal_seam_weight)]
+    normal_seam_weight: f32,
+    #[arg(long, default_value_t = ChartOptions::default().texture_seam_weight)]
+    texture_seam_weight: f32,
+    /// Don't grow a chart if the weighted cost exceeds this. Lower values result in more charts.
+    #[arg(long, default_value_t = ChartOptions::default().max_cost)]
+    max_cost: f32,
+    /// Iterations of chart growing and seeding. Higher values result in better charts.
+    #[arg(long, default_value_t = ChartOptions::default().max_iterations)]
+    max_iterations: u32,
+    /// Use the input UVs for charts.
+    #[arg(long)]
+    use_input_mesh_uvs: bool,
+    /// Enforce consistent texture coordinate winding.
+    #[arg(long)]
+    fix_winding: bool,
+}
+
+#[derive(clap::Args)]
+#[command(next_help_heading = "Pack options")]
+struct PackArgs {
+    /// Charts larger than this are scaled down. 0 means no limit.
+    #[arg(long, default_value_t = PackOptions::default().max_chart_size)]
+    max_chart_size: u32,
+    /// Texels of padding around charts.
+    #[arg(long, default_value_t = PackOptions::default().padding)]
+    padding: u32,
+    /// Texels per world unit. 0 estimates it from the resolution.
+    #[arg(long, default_value_t = PackOptions::default().texels_per_unit)]
+    texels_per_unit: f32,
+    /// Atlas resolution. 0 makes a single atlas sized by texels per unit.
+    #[arg(long, default_value_t = PackOptions::default().resolution)]
+    resolution: u32,
+    /// Don't leave space around charts for bilinear filtering.
+    #[arg(long)]
+    no_bilinear: bool,
+    /// Align charts to 4x4 blocks.
+    #[arg(long)]
+    block_align: bool,
+    /// Slower, but gives the best packing.
+    #[arg(long)]
+    brute_force: bool,
+    /// Don't rotate charts to the axis of their convex hull.
+    #[arg(long)]
+    no_rotate_charts_to_axis: bool,
+    /// Don't rotate charts to improve packing.
+    #[arg(long)]
+    no_rotate_charts: bool,
+}
+
+/// A loaded input file, which owns the data its mesh declarations borrow.
+enum Input {
+    Obj(ObjMesh),
+    Ply(PlyMesh),
+    Stl(StlMesh),
+    Gltf(Box<GltfScene>),
+}
+
+impl Input {
+    fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        Ok(match extension.as_str() {
+            "obj" => Input::Obj(ObjMesh::load(path)?),
+            "ply" => Input::Ply(PlyMesh::load(path)?),
+            "stl" => Input::Stl(StlMesh::load(path)?),
+            "gltf" | "glb" => Input::Gltf(Box::new(GltfScene::load(path)?)),
+            _ => return Err(format!("unsupported file type '{extension}'").into()),
+        })
+    }
+
+    fn mesh_decls(&self) -> Vec<MeshDecl<'_>> {
+        match self {
+            Input::Obj(mesh) => vec![mesh.mesh_decl()],
+            Input::Ply(mesh) => vec![mesh.mesh_decl()],
+            Input::Stl(mesh) => vec![mesh.mesh_decl()],
+            Input::Gltf(scene) => scene.mesh_decls(),
+        }
+    }
+}
+
+impl ChartArgs {
+    fn options(&self) -> ChartOptions {
+        ChartOptions {
+            max_chart_area: self.max_chart_area,
+            max_boundary_length: self.max_boundary_length,
+            normal_deviation_weight: self.normal_deviation_weight,
+            roundness_weight: self.roundness_weight,
+            straightness_weight: self.straightness_weight,
+            normal_seam_weight: self.normal_seam_weight,
+            texture_seam_weight: self.texture_seam_weight,
+            max_cost: self.max_cost,
+            max_iterations: self.max_iterations,
+            use_input_mesh_uvs: self.use_input_mesh_uvs,
+            fix_winding: self.fix_winding,
+            ..ChartOptions::default()
+        }
+    }
+}
+
+impl PackArgs {
+    fn options(&self, create_image: bool) -> PackOptions {
+        PackOptions {
+            max_chart_size: self.max_chart_size,
+            padding: self.padding,
+            texels_per_unit: self.texels_per_unit,
+            resolution: self.resolution,
+            bilinear: !self.no_bilinear,
+            block_align: self.block_align,
+            brute_force: self.brute_force,
+            create_image,
+            rotate_charts_to_axis: !self.no_rotate_charts_to_axis,
+            rotate_charts: !self.no_rotate_charts,
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    let mut failed = false;
+    for input in &args.inputs {
+        if let Err(e) = unwrap(&args, input) {
+            eprintln!("{}: {e}", input.display());
+            failed = true;
+        }
+    }
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn unwrap(args: &Args, path: &Path) -> Result<(), Box<dyn Error>> {
+    let input = Input::load(path)?;
+    let decls = input.mesh_decls();
+
+    let mut atlas = Xatlas::new();
+    for decl in &decls {
+        atlas.add_mesh_with_mesh_count_hint(decl, decls.len() as u32)?;
+    }
+    atlas.generate(&args.chart.options(), &args.pack.options(!args.no_image))?;
+    let output = atlas.into_output();
+
+    let directory = match &args.output_dir {
+        Some(directory) => {
+            fs::create_dir_all(directory).map_err(|error| {
+                format!(
+                    "can't create output directory {}: {error}",
+                    directory.display()
+                )
+            })?;
+            directory.as_path()
+        }
+        None => path.parent().unwrap_or_else(|| Path::new("")),
+    };
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let mesh_path = match &input {
+        Input::Gltf(scene) => {
+            let mesh_path = directory.join(format!("{stem}_unwrapped.glb"));
+            scene.save_glb(&mesh_path, &output)?;
+            mesh_path
+        }
+        _ => {
+            let mesh_path = directory.join(format!("{stem}_unwrapped.obj"));
+            let groups = match args.obj_groups {
+                Groups::None => ObjGroups::None,
+                Groups::Atlas => ObjGroups::Atlas,
+                Groups::Chart => ObjGroups::Chart,
+            };
+            obj::save(&mesh_path, &decls, &output, &ObjWriteOptions { groups })?;
+            mesh_path
+        }
+    };
+    println!("{}", mesh_path.display());
+
+    for atlas_index in 0..output.atlas_count {
+        if let Some(page) = output.image_page(atlas_index) {
+            let image_path = directory.join(format!("{stem}_atlas{atlas_index}.png"));
+            page.save_png(&image_path)?;
+            println!("{}", image_path.display());
+        }
+    }
+
+    println!(
+        "{} charts in {} atlas(es) of {}x{}, {} texels per unit",
+        output.chart_count, output.atlas_count, output.width, output.height, output.texels_per_unit
+    );
+    for (atlas_index, utilization) in output.utilization.iter().enumerate() {
+        println!(
+            "  atlas {atlas_index}: {:.1}% utilization",
+            utilization * 100.0
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,219 @@
+//! Texel density each chart ended up with after packing, compared to `texels_per_unit`.
+//!
+//! Packing scales charts uniformly by `texels_per_unit`, except for charts larger than
+//! `PackOptions::max_chart_size`, which are scaled down further, and atlases that don't fit
+//! the resolution. The density of a chart is derived from its texel area and surface area.
+
+use crate::{AtlasMetrics, AtlasOutput, ChartRef, DistortionMetrics, Mesh, MeshDecl};
+
+/// Texel density of one `Chart`, in the order of `Mesh::chart_array`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChartDensity {
+    pub atlas_index: u32,
+    /// Texels per world unit, the square root of the texel area over the surface area.
+    /// 0 for charts without area.
+    pub texels_per_unit: f32,
+    /// `texels_per_unit` divided by the target density. Charts that were scaled down have a
+    /// ratio below 1.
+    pub ratio: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeshDensity {
+    pub charts: Vec<ChartDensity>,
+    /// Density over all charts of the mesh, weighted by surface area.
+    pub texels_per_unit: f32,
+    /// Lowest and highest density of a chart with area.
+    pub min_texels_per_unit: f32,
+    pub max_texels_per_unit: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DensityReport {
+    /// The density charts were packed for, `AtlasOutput::texels_per_unit`.
+    pub target: f32,
+    pub meshes: Vec<MeshDensity>,
+    /// Density over all charts, weighted by surface area.
+    pub texels_per_unit: f32,
+}
+
+impl DensityReport {
+    /// Computes the density of every chart. The surface areas come from `inputs`, which pair
+    /// with the output meshes as described on [`AtlasOutput::meshes`].
+    ///
+    /// # Panics
+    ///
+    /// If `inputs` doesn't match the output meshes.
+    pub fn compute(inputs: &[MeshDecl<'_>], output: &AtlasOutput) -> Self {
+        Self::from_meshes(inputs, &output.meshes, output.texels_per_unit)
+    }
+
+    /// `compute` for meshes that are still borrowed from the atlas, see `Xatlas::meshes`.
+    pub(crate) fn from_meshes(inputs: &[MeshDecl<'_>], meshes: &[Mesh<'_>], target: f32) -> Self {
+        Self::from_metrics(&AtlasMetrics::from_meshes(inputs, meshes), target)
+    }
+
+    /// Derives the density from already computed metrics, compared to `target`.
+    pub fn from_metrics(metrics: &AtlasMetrics, target: f32) -> Self {
+        let meshes = metrics
+            .meshes
+            .iter()
+            .map(|mesh| {
+                let charts: Vec<ChartDensity> = mesh
+                    .charts
+                    .iter()
+                    .map(|chart| {
+                        let texels_per_unit = density(&chart.distortion);
+                        ChartDensity {
+                            atlas_index: chart.atlas_index,
+                            texels_per_unit,
+                            ratio: if target > 0.0 {
+                                texels_per_unit / target
+                            } else {
+                                0.0
+                            },
+                        }
+                    })
+                    .collect();
+                let with_area = || {
+                    charts
+                        .iter()
+                        .map(|chart| chart.texels_per_unit)
+                        .filter(|&texels_per_unit| texels_per_unit > 0.0)
+                };
+                MeshDensity {
+                    texels_per_unit: density(&mesh.distortion),
+                    min_texels_per_unit: with_area().reduce(f32::min).unwrap_or(0.0),
+                    max_texels_per_unit: with_area().reduce(f32::max).unwrap_or(0.0),
+                    charts,
+                }
+            })
+            .collect();
+        DensityReport {
+            target,
+            meshes,
+            texels_per_unit: density(&metrics.distortion),
+        }
+    }
+
+    /// Charts whose density differs from the target by more than `tolerance`, relative to the
+    /// target. A tolerance of 0.1 accepts charts between 90% and 110% of the target density.
+    /// Charts without area are never reported.
+    pub fn deviating(&self, tolerance: f32) -> Vec<ChartRef> {
+        self.meshes
+            .iter()
+            .enumerate()
+            .flat_map(|(mesh_index, mesh)| {
+                mesh.charts
+                    .iter()
+                    .enumerate()
+                    .filter(move |(_, chart)| {
+                        chart.texels_per_unit > 0.0 && (chart.ratio - 1.0).abs() > tolerance
+                    })
+                    .map(move |(chart_index, _)| ChartRef {
+                        mesh: mesh_index as u32,
+                        chart: chart_index as u32,
+                    })
+            })
+            .collect()
+    }
+
+    pub fn is_uniform(&self, tolerance: f32) -> bool {
+        self.deviating(tolerance).is_empty()
+    }
+}
+
+fn density(distortion: &DistortionMetrics) -> f32 {
+    if distortion.area > 0.0 {
+        (distortion.uv_area / distortion.area).sqrt()
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CancellationToken, Chart, ChartType, PackOptions, Vertex, Xatlas};
+    use std::borrow::Cow;
+
+    /// Two unit right triangles side by side on the surface.
+    const POSITIONS: [[f32; 3]; 6] = [
+        [0.0, 0.0, 0.0],
+        [1.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0],
+        [2.0, 0.0, 0.0],
+        [3.0, 0.0, 0.0],
+        [2.0, 1.0, 0.0],
+    ];
+
+    /// One chart per triangle, each packed at `texels_per_unit[chart]`, for a target of 4.
+    fn output(texels_per_unit: [f32; 2]) -> AtlasOutput {
+        let mut vertex_array = Vec::new();
+        for (chart_index, &scale) in texels_per_unit.iter().enumerate() {
+            for (corner, uv) in [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]].iter().enumerate() {
+                vertex_array.push(Vertex {
+                    atlas_index: 0,
+                    chart_index: chart_index as i32,
+                    uv: [uv[0] * scale + chart_index as f32 * 8.0, uv[1] * scale],
+                    xref: (chart_index * 3 + corner) as u32,
+                });
+            }
+        }
+        let chart = |face| Chart {
+            face_array: Cow::Owned(vec![face]),
+            atlas_index: 0,
+            type_: ChartType::Planar,
+            material: 0,
+        };
+        AtlasOutput {
+            width: 16,
+            height: 16,
+            atlas_count: 1,
+            chart_count: 2,
+            texels_per_unit: 4.0,
+            utilization: vec![0.0],
+            image: None,
+            meshes: vec![Mesh {
+                index_array: Cow::Owned((0..6).collect()),
+                chart_array: vec![chart(0), chart(1)],
+                vertex_array,
+            }],
+        }
+    }
+
+    #[test]
+    fn charts_beyond_the_tolerance_deviate() {
+        let report = DensityReport::compute(&[MeshDecl::new(&POSITIONS[..])], &output([4.0, 3.0]));
+        let charts = &report.meshes[0].charts;
+        assert!((charts[0].ratio - 1.0).abs() < 1e-5);
+        assert!((charts[1].texels_per_unit - 3.0).abs() < 1e-5);
+        assert!((charts[1].ratio - 0.75).abs() < 1e-5);
+        assert_eq!(
+            report.meshes[0].min_texels_per_unit,
+            charts[1].texels_per_unit
+        );
+
+        assert_eq!(report.deviating(0.1), [ChartRef { mesh: 0, chart: 1 }]);
+        assert!(!report.is_uniform(0.1));
+        assert!(report.is_uniform(0.3));
+    }
+
+    #[test]
+    fn charts_within_the_tolerance_are_uniform() {
+        let report = DensityReport::compute(&[MeshDecl::new(&POSITIONS[..])], &output([4.0, 4.2]));
+        assert!(report.deviating(0.1).is_empty());
+        assert!(report.is_uniform(0.1));
+    }
+
+    #[test]
+    fn uniform_density_is_not_repacked() {
+        // Re-packing would be cancelled by the token before it starts.
+        let mut atlas = Xatlas::new();
+        let token = CancellationToken::new();
+        token.cancel();
+        atlas.set_cancellation_token(Some(token));
+        let report = atlas.equalize_texel_density(&[], &PackOptions::default(), 0.1);
+        assert!(report.is_ok_and(|report| report.meshes.is_empty()));
+    }
+}
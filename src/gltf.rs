@@ -0,0 +1,914 @@
+//! glTF 2.0 / GLB import, and export of the unwrapped primitives with the atlas UVs stored as
+//! `TEXCOORD_1`, and the atlas page of every vertex as `_ATLAS_INDEX` when there are several.
+//!
+//! ```no_run
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use xatlas_rs::{ChartOptions, PackOptions, Xatlas};
+//!
+//! let scene = xatlas_rs::gltf::GltfScene::load("model.glb")?;
+//! let mut atlas = Xatlas::new();
+//! scene.add_to(&mut atlas)?;
+//! atlas.generate(&ChartOptions::default(), &PackOptions::default())?;
+//! scene.save_glb("model_unwrapped.glb", &atlas.to_output())?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{AddMeshError, AtlasOutput, MeshDecl, Xatlas};
+use ::gltf::accessor::sparse::IndexType;
+use ::gltf::accessor::{Accessor, DataType};
+use ::gltf::mesh::Mode;
+use ::gltf::{Document, Gltf, Node, Semantic};
+use serde_json::{json, Value};
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+/// A glTF asset whose triangle primitives are prepared for unwrapping. Every primitive becomes
+/// one xatlas mesh, in the order of `primitives`.
+#[derive(Debug, Clone)]
+pub struct GltfScene {
+    document: Document,
+    /// The JSON as it was loaded, so extensions and extras this module doesn't know about
+    /// survive the round trip.
+    json: Value,
+    buffers: Vec<Vec<u8>>,
+    primitives: Vec<GltfPrimitive>,
+}
+
+/// A triangle primitive, with positions and normals in world space. Strips and fans are
+/// converted to triangle lists.
+#[derive(Debug, Clone)]
+pub struct GltfPrimitive {
+    /// Index of the glTF mesh.
+    pub mesh: usize,
+    /// Index of the primitive in the mesh.
+    pub primitive: usize,
+    pub positions: Vec<[f32; 3]>,
+    /// Empty if the primitive doesn't have normals.
+    pub normals: Vec<[f32; 3]>,
+    /// `TEXCOORD_0`, empty if the primitive doesn't have it.
+    pub uvs: Vec<[f32; 2]>,
+    pub indices: Vec<u32>,
+}
+
+#[derive(Debug)]
+pub enum GltfError {
+    Io(io::Error),
+    Gltf(::gltf::Error),
+    Json(serde_json::Error),
+    AddMesh(AddMeshError),
+    /// The asset uses a feature this module doesn't handle, e.g. Draco compression.
+    Unsupported(String),
+    /// The asset references data that doesn't exist.
+    Invalid(String),
+}
+
+impl GltfScene {
+    /// Loads a `.gltf` or `.glb` file. External buffers are looked up relative to the file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, GltfError> {
+        let path = path.as_ref();
+        let data = fs::read(path)?;
+        Self::from_slice(&data, path.parent())
+    }
+
+    /// Parses glTF JSON or GLB data. `base` is the directory external buffers are loaded from,
+    /// without it only embedded buffers and data URIs are supported.
+    pub fn from_slice(data: &[u8], base: Option<&Path>) -> Result<Self, GltfError> {
+        let Gltf { document, blob } = Gltf::from_slice(data)?;
+        let json = if data.starts_with(b"glTF") {
+            serde_json::from_slice(&::gltf::Glb::from_slice(data)?.json)?
+        } else {
+            serde_json::from_slice(data)?
+        };
+
+        if let Some(extension) = document.extensions_required().find(|extension| {
+            *extension == "KHR_draco_mesh_compression" || *extension == "EXT_meshopt_compression"
+        }) {
+            return Err(GltfError::Unsupported(format!(
+                "the {extension} extension is required"
+            )));
+        }
+
+        let buffers = ::gltf::import_buffers(&document, base, blob)?
+            .into_iter()
+            .zip(document.buffers())
+            .map(|(data, buffer)| {
+                // The importer pads buffers to 4 bytes, the GLB writer adds its own padding.
+                let mut data = data.0;
+                data.truncate(buffer.length());
+                data
+            })
+            .collect();
+
+        let mut scene = GltfScene {
+            document,
+            json,
+            buffers,
+            primitives: Vec::new(),
+        };
+        scene.primitives = scene.read_primitives()?;
+        Ok(scene)
+    }
+
+    pub fn primitives(&self) -> &[GltfPrimitive] {
+        &self.primitives
+    }
+
+    /// A mesh declaration for every primitive, for passing to `Xatlas::add_mesh`.
+    pub fn mesh_decls(&self) -> Vec<MeshDecl<'_>> {
+        self.primitives
+            .iter()
+            .map(GltfPrimitive::mesh_decl)
+            .collect()
+    }
+
+    /// Adds all primitives to the atlas.
+    pub fn add_to(&self, atlas: &mut Xatlas) -> Result<(), AddMeshError> {
+        let count = self.primitives.len() as u32;
+        for primitive in &self.primitives {
+            atlas.add_mesh_with_mesh_count_hint(&primitive.mesh_decl(), count)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the asset as GLB, with every unwrapped primitive re-indexed according to the
+    /// output mesh. Its vertex attributes and morph targets are remapped through `Vertex::xref`,
+    /// and the atlas UVs, normalized by the atlas size, replace `TEXCOORD_1`. If the output has
+    /// more than one atlas, the `_ATLAS_INDEX` float attribute holds the atlas every vertex is
+    /// on, -1 for vertices of ignored faces.
+    ///
+    /// All buffers are merged into the GLB's BIN chunk. The original accessors are kept, since
+    /// skins and animations may still refer to them. Images referenced by URI aren't copied.
+    pub fn write_glb(&self, mut writer: impl Write, output: &AtlasOutput) -> Result<(), GltfError> {
+        if self.primitives.len() != output.meshes.len() {
+            return Err(GltfError::Invalid(format!(
+                "{} primitives for {} output meshes",
+                self.primitives.len(),
+                output.meshes.len()
+            )));
+        }
+
+        let mut json = self.json.clone();
+        let mut bin = Vec::new();
+        let mut buffer_offsets = Vec::with_capacity(self.buffers.len());
+        for buffer in &self.buffers {
+            buffer_offsets.push(bin.len());
+            bin.extend_from_slice(buffer);
+            pad(&mut bin, 0);
+        }
+        if let Some(views) = json["bufferViews"].as_array_mut() {
+            for view in views {
+                let buffer = view["buffer"].as_u64().unwrap_or(0) as usize;
+                let offset = view["byteOffset"].as_u64().unwrap_or(0) as usize;
+                view["buffer"] = json!(0);
+                view["byteOffset"] = json!(buffer_offsets[buffer] + offset);
+            }
+        }
+
+        let mut builder = GlbBuilder {
+            json: &mut json,
+            bin: &mut bin,
+        };
+        let scale = [
+            1.0 / output.width.max(1) as f32,
+            1.0 / output.height.max(1) as f32,
+        ];
+        for (input, mesh) in self.primitives.iter().zip(&output.meshes) {
+            let xref: Vec<u32> = mesh.vertex_array.iter().map(|vertex| vertex.xref).collect();
+            let primitive_json = &self.json["meshes"][input.mesh]["primitives"][input.primitive];
+
+            // Attributes are taken from the JSON, so custom attributes like `_BATCHID` are kept.
+            let mut attributes = primitive_json["attributes"].clone();
+            if let Some(attributes) = attributes.as_object_mut() {
+                attributes.remove("TEXCOORD_1");
+                attributes.remove("_ATLAS_INDEX");
+            }
+            let mut attributes = self.remap_attributes(&mut builder, &attributes, &xref)?;
+
+            let mut uvs = Vec::with_capacity(mesh.vertex_array.len() * 8);
+            for vertex in &mesh.vertex_array {
+                uvs.extend_from_slice(&(vertex.uv[0] * scale[0]).to_le_bytes());
+                uvs.extend_from_slice(&(vertex.uv[1] * scale[1]).to_le_bytes());
+            }
+            let view = builder.add_view(&uvs, Some(ARRAY_BUFFER), None);
+            let index = builder.add_accessor(json!({
+                "bufferView": view,
+                "componentType": 5126,
+                "count": mesh.vertex_array.len(),
+                "type": "VEC2",
+            }));
+            attributes.insert("TEXCOORD_1".to_owned(), json!(index));
+
+            if output.atlas_count > 1 {
+                let pages: Vec<u8> = mesh
+                    .vertex_array
+                    .iter()
+                    .flat_map(|vertex| (vertex.atlas_index as f32).to_le_bytes())
+                    .collect();
+                let view = builder.add_view(&pages, Some(ARRAY_BUFFER), None);
+                let index = builder.add_accessor(json!({
+                    "bufferView": view,
+                    "componentType": 5126,
+                    "count": mesh.vertex_array.len(),
+                    "type": "SCALAR",
+                }));
+                attributes.insert("_ATLAS_INDEX".to_owned(), json!(index));
+            }
+
+            let mut targets = Vec::new();
+            for target in primitive_json["targets"].as_array().into_iter().flatten() {
+                let target = self.remap_attributes(&mut builder, target, &xref)?;
+                targets.push(Value::Object(target));
+            }
+
+            let indices = if mesh.vertex_array.len() < u16::MAX as usize {
+                let data: Vec<u8> = mesh
+                    .index_array
+                    .iter()
+                    .flat_map(|&index| (index as u16).to_le_bytes())
+                    .collect();
+                (data, 5123)
+            } else {
+                let data: Vec<u8> = mesh
+                    .index_array
+                    .iter()
+                    .flat_map(|&index| index.to_le_bytes())
+                    .collect();
+                (data, 5125)
+            };
+            let view = builder.add_view(&indices.0, Some(ELEMENT_ARRAY_BUFFER), None);
+            let index = builder.add_accessor(json!({
+                "bufferView": view,
+                "componentType": indices.1,
+                "count": mesh.index_array.len(),
+                "type": "SCALAR",
+            }));
+
+            let primitive = &mut builder.json["meshes"][input.mesh]["primitives"][input.primitive];
+            primitive["attributes"] = Value::Object(attributes);
+            primitive["indices"] = json!(index);
+            // Strips and fans were read as triangle lists.
+            primitive["mode"] = json!(4);
+            if !targets.is_empty() {
+                primitive["targets"] = Value::Array(targets);
+            }
+        }
+
+        json["buffers"] = json!([{ "byteLength": bin.len() }]);
+        let mut json = serde_json::to_vec(&json)?;
+        pad(&mut json, b' ');
+        pad(&mut bin, 0);
+
+        let length = 12 + 8 + json.len() + if bin.is_empty() { 0 } else { 8 + bin.len() };
+        let length = u32::try_from(length)
+            .map_err(|_| GltfError::Unsupported("the GLB would exceed 4 GiB".to_owned()))?;
+        writer.write_all(b"glTF")?;
+        writer.write_all(&2u32.to_le_bytes())?;
+        writer.write_all(&length.to_le_bytes())?;
+        writer.write_all(&(json.len() as u32).to_le_bytes())?;
+        writer.write_all(b"JSON")?;
+        writer.write_all(&json)?;
+        if !bin.is_empty() {
+            writer.write_all(&(bin.len() as u32).to_le_bytes())?;
+            writer.write_all(b"BIN\0")?;
+            writer.write_all(&bin)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn save_glb(&self, path: impl AsRef<Path>, output: &AtlasOutput) -> Result<(), GltfError> {
+        self.write_glb(BufWriter::new(File::create(path)?), output)
+    }
+
+    fn read_primitives(&self) -> Result<Vec<GltfPrimitive>, GltfError> {
+        // Positions are transformed by the node that instances the mesh, so the texel density
+        // matches the size the mesh is rendered at.
+        let mut transforms = vec![None; self.document.meshes().len()];
+        for scene in self.document.scenes() {
+            for node in scene.nodes() {
+                collect_transforms(&node, IDENTITY, &mut transforms)?;
+            }
+        }
+
+        let mut primitives = Vec::new();
+        for mesh in self.document.meshes() {
+            let transform = transforms[mesh.index()].map_or(IDENTITY, |(_, transform)| transform);
+            let normal_transform = normal_matrix(&transform);
+            for primitive in mesh.primitives() {
+                let position_accessor = match primitive.get(&Semantic::Positions) {
+                    Some(accessor) => accessor,
+                    None => continue,
+                };
+                match primitive.mode() {
+                    Mode::Triangles | Mode::TriangleStrip | Mode::TriangleFan => {}
+                    // Points and lines have no surface to unwrap and are written out unchanged.
+                    _ => continue,
+                }
+
+                let positions = self
+                    .read_floats::<3>(&position_accessor)?
+                    .into_iter()
+                    .map(|position| transform_point(&transform, position))
+                    .collect::<Vec<_>>();
+                let normals = match primitive.get(&Semantic::Normals) {
+                    None => Vec::new(),
+                    Some(accessor) => self
+                        .read_floats::<3>(&accessor)?
+                        .into_iter()
+                        .map(|normal| transform_normal(&normal_transform, normal))
+                        .collect(),
+                };
+                let uvs = match primitive.get(&Semantic::TexCoords(0)) {
+                    None => Vec::new(),
+                    Some(accessor) => self.read_floats::<2>(&accessor)?,
+                };
+                let indices = match primitive.indices() {
+                    None => (0..positions.len() as u32).collect(),
+                    Some(accessor) => self.read_indices(&accessor)?,
+                };
+                let indices = triangle_list(primitive.mode(), indices);
+
+                primitives.push(GltfPrimitive {
+                    mesh: mesh.index(),
+                    primitive: primitive.index(),
+                    positions,
+                    normals,
+                    uvs,
+                    indices,
+                });
+            }
+        }
+        Ok(primitives)
+    }
+
+    /// The elements of an accessor, tightly packed and with sparse substitutions applied.
+    fn read_accessor(&self, accessor: &Accessor<'_>) -> Result<Vec<u8>, GltfError> {
+        let size = accessor.size();
+        let count = accessor.count();
+        let mut data = vec![0; size * count];
+
+        if let Some(view) = accessor.view() {
+            let stride = view.stride().unwrap_or(size);
+            let start = view.offset() + accessor.offset();
+            let source = self.view_bytes(&view, start, stride * count.saturating_sub(1) + size)?;
+            for (i, element) in data.chunks_exact_mut(size).enumerate() {
+                element.copy_from_slice(&source[i * stride..i * stride + size]);
+            }
+        }
+
+        if let Some(sparse) = accessor.sparse() {
+            let indices = sparse.indices();
+            let index_size = match indices.index_type() {
+                IndexType::U8 => 1,
+                IndexType::U16 => 2,
+                IndexType::U32 => 4,
+            };
+            let index_view = indices.view();
+            let index_bytes = self.view_bytes(
+                &index_view,
+                index_view.offset() + indices.offset(),
+                index_size * sparse.count(),
+            )?;
+            let value_view = sparse.values().view();
+            let values = self.view_bytes(
+                &value_view,
+                value_view.offset() + sparse.values().offset(),
+                size * sparse.count(),
+            )?;
+            for (i, value) in values.chunks_exact(size).enumerate() {
+                let index = read_uint(&index_bytes[i * index_size..], index_size) as usize;
+                let target = data
+                    .get_mut(index * size..(index + 1) * size)
+                    .ok_or_else(|| {
+                        GltfError::Invalid(format!(
+                            "sparse index {index} of accessor {} is out of range",
+                            accessor.index()
+                        ))
+                    })?;
+                target.copy_from_slice(value);
+            }
+        }
+
+        Ok(data)
+    }
+
+    fn view_bytes(
+        &self,
+        view: &::gltf::buffer::View<'_>,
+        start: usize,
+        length: usize,
+    ) -> Result<&[u8], GltfError> {
+        self.buffers[view.buffer().index()]
+            .get(start..start + length)
+            .ok_or_else(|| {
+                GltfError::Invalid(format!("buffer view {} is out of range", view.index()))
+            })
+    }
+
+    /// Reads an accessor as floats, converting normalized integers as the spec describes.
+    fn read_floats<const N: usize>(
+        &self,
+        accessor: &Accessor<'_>,
+    ) -> Result<Vec<[f32; N]>, GltfError> {
+        if accessor.dimensions().multiplicity() != N {
+            return Err(GltfError::Invalid(format!(
+                "accessor {} has {} components, expected {N}",
+                accessor.index(),
+                accessor.dimensions().multiplicity()
+            )));
+        }
+        let data_type = accessor.data_type();
+        let normalized = accessor.normalized();
+        let data = self.read_accessor(accessor)?;
+        Ok(data
+            .chunks_exact(accessor.size())
+            .map(|element| {
+                let mut value = [0.0; N];
+                for (i, component) in element.chunks_exact(data_type.size()).enumerate() {
+                    value[i] = read_component(component, data_type, normalized);
+                }
+                value
+            })
+            .collect())
+    }
+
+    fn read_indices(&self, accessor: &Accessor<'_>) -> Result<Vec<u32>, GltfError> {
+        let size = accessor.data_type().size();
+        let data = self.read_accessor(accessor)?;
+        Ok(data
+            .chunks_exact(size)
+            .map(|index| read_uint(index, size))
+            .collect())
+    }
+
+    /// Remaps every accessor of an attribute or morph target object.
+    fn remap_attributes(
+        &self,
+        builder: &mut GlbBuilder<'_>,
+        attributes: &Value,
+        xref: &[u32],
+    ) -> Result<serde_json::Map<String, Value>, GltfError> {
+        let mut remapped = serde_json::Map::new();
+        for (name, accessor) in attributes.as_object().into_iter().flatten() {
+            let accessor = accessor
+                .as_u64()
+                .and_then(|index| self.document.accessors().nth(index as usize))
+                .ok_or_else(|| GltfError::Invalid(format!("attribute {name} has no accessor")))?;
+            let index = self.remap_accessor(builder, &accessor, xref, name == "POSITION")?;
+            remapped.insert(name.clone(), json!(index));
+        }
+        Ok(remapped)
+    }
+
+    /// Writes the elements of `accessor` selected by `xref` as a new accessor and returns its index.
+    fn remap_accessor(
+        &self,
+        builder: &mut GlbBuilder<'_>,
+        accessor: &Accessor<'_>,
+        xref: &[u32],
+        bounds: bool,
+    ) -> Result<usize, GltfError> {
+        let size = accessor.size();
+        let data = self.read_accessor(accessor)?;
+        // Vertex attribute elements have to start at 4 byte boundaries.
+        let stride = (size + 3) & !3;
+        let mut remapped = vec![0; stride * xref.len()];
+        for (element, &xref) in remapped.chunks_exact_mut(stride).zip(xref) {
+            let source = data
+                .get(xref as usize * size..(xref as usize + 1) * size)
+                .ok_or_else(|| {
+                    GltfError::Invalid(format!(
+                        "vertex {xref} is out of range for accessor {}",
+                        accessor.index()
+                    ))
+                })?;
+            element[..size].copy_from_slice(source);
+        }
+
+        let view = builder.add_view(
+            &remapped,
+            Some(ARRAY_BUFFER),
+            (stride != size).then_some(stride),
+        );
+        let mut json = self.json["accessors"][accessor.index()].clone();
+        if let Some(object) = json.as_object_mut() {
+            object.remove("sparse");
+            object.remove("byteOffset");
+            object.insert("bufferView".to_owned(), json!(view));
+            object.insert("count".to_owned(), json!(xref.len()));
+            // Only position bounds are required. Quantized positions keep their original
+            // bounds, which still contain the remapped data.
+            if !bounds {
+                object.remove("min");
+                object.remove("max");
+            } else if accessor.data_type() == DataType::F32 && !xref.is_empty() {
+                let components = accessor.dimensions().multiplicity();
+                let mut min = vec![f32::INFINITY; components];
+                let mut max = vec![f32::NEG_INFINITY; components];
+                for element in remapped.chunks_exact(stride) {
+                    for (i, component) in element[..size].chunks_exact(4).enumerate() {
+                        let value = read_component(component, DataType::F32, false);
+                        min[i] = min[i].min(value);
+                        max[i] = max[i].max(value);
+                    }
+                }
+                object.insert("min".to_owned(), json!(min));
+                object.insert("max".to_owned(), json!(max));
+            }
+        }
+        Ok(builder.add_accessor(json))
+    }
+}
+
+impl GltfPrimitive {
+    pub fn mesh_decl(&self) -> MeshDecl<'_> {
+        MeshDecl::from_polygons(
+            &self.positions,
+            &self.normals,
+            &self.uvs,
+            &self.indices,
+            &[],
+        )
+    }
+}
+
+/// Appends buffer views and accessors to the JSON of the GLB being written.
+struct GlbBuilder<'a> {
+    json: &'a mut Value,
+    bin: &'a mut Vec<u8>,
+}
+
+impl GlbBuilder<'_> {
+    fn add_view(&mut self, data: &[u8], target: Option<u32>, stride: Option<usize>) -> usize {
+        pad(self.bin, 0);
+        let mut view = json!({
+            "buffer": 0,
+            "byteOffset": self.bin.len(),
+            "byteLength": data.len(),
+        });
+        if let Some(target) = target {
+            view["target"] = json!(target);
+        }
+        if let Some(stride) = stride {
+            view["byteStride"] = json!(stride);
+        }
+        self.bin.extend_from_slice(data);
+        push(self.json, "bufferViews", view)
+    }
+
+    fn add_accessor(&mut self, accessor: Value) -> usize {
+        push(self.json, "accessors", accessor)
+    }
+}
+
+/// Appends to a top level array of the glTF JSON, creating it if needed, and returns the index.
+fn push(json: &mut Value, key: &str, value: Value) -> usize {
+    if !json[key].is_array() {
+        json[key] = json!([]);
+    }
+    let array = json[key].as_array_mut().expect("just made an array");
+    array.push(value);
+    array.len() - 1
+}
+
+fn pad(data: &mut Vec<u8>, byte: u8) {
+    while !data.len().is_multiple_of(4) {
+        data.push(byte);
+    }
+}
+
+fn read_uint(bytes: &[u8], size: usize) -> u32 {
+    match size {
+        1 => bytes[0] as u32,
+        2 => u16::from_le_bytes([bytes[0], bytes[1]]) as u32,
+        _ => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+    }
+}
+
+fn read_component(bytes: &[u8], data_type: DataType, normalized: bool) -> f32 {
+    let (value, max) = match data_type {
+        DataType::I8 => (bytes[0] as i8 as f32, i8::MAX as f32),
+        DataType::U8 => (bytes[0] as f32, u8::MAX as f32),
+        DataType::I16 => (
+            i16::from_le_bytes([bytes[0], bytes[1]]) as f32,
+            i16::MAX as f32,
+        ),
+        DataType::U16 => (
+            u16::from_le_bytes([bytes[0], bytes[1]]) as f32,
+            u16::MAX as f32,
+        ),
+        DataType::U32 => (read_uint(bytes, 4) as f32, u32::MAX as f32),
+        DataType::F32 => return f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+    };
+    if normalized {
+        (value / max).max(-1.0)
+    } else {
+        value
+    }
+}
+
+/// Converts the indices of a strip or fan to a triangle list, as the glTF spec defines them.
+/// Degenerate strip triangles, which are used to join strips, are dropped.
+fn triangle_list(mode: Mode, indices: Vec<u32>) -> Vec<u32> {
+    let corners = indices.len().saturating_sub(2);
+    match mode {
+        Mode::TriangleStrip => (0..corners)
+            .map(|i| {
+                if i % 2 == 0 {
+                    [indices[i], indices[i + 1], indices[i + 2]]
+                } else {
+                    [indices[i], indices[i + 2], indices[i + 1]]
+                }
+            })
+            .filter(|[a, b, c]| a != b && b != c && a != c)
+            .flatten()
+            .collect(),
+        Mode::TriangleFan => (0..corners)
+            .flat_map(|i| [indices[i + 1], indices[i + 2], indices[0]])
+            .collect(),
+        _ => indices,
+    }
+}
+
+/// Column major 4x4 matrix, as used by glTF.
+type Matrix = [[f32; 4]; 4];
+
+const IDENTITY: Matrix = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+/// Finds the node that instances every mesh and its world transform. A mesh instanced by several
+/// nodes would need an atlas region per instance, so it is rejected.
+fn collect_transforms(
+    node: &Node<'_>,
+    parent: Matrix,
+    transforms: &mut [Option<(usize, Matrix)>],
+) -> Result<(), GltfError> {
+    let world = multiply(&parent, &node.transform().matrix());
+    if let Some(mesh) = node.mesh() {
+        match transforms[mesh.index()] {
+            None => transforms[mesh.index()] = Some((node.index(), world)),
+            Some((other, _)) if other != node.index() => {
+                return Err(GltfError::Unsupported(format!(
+                    "mesh {} is instanced by nodes {other} and {}",
+                    mesh.index(),
+                    node.index()
+                )))
+            }
+            Some(_) => {}
+        }
+    }
+    for child in node.children() {
+        collect_transforms(&child, world, transforms)?;
+    }
+    Ok(())
+}
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut result = [[0.0; 4]; 4];
+    for (column, b_column) in result.iter_mut().zip(b) {
+        for (row, value) in column.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[k][row] * b_column[k]).sum();
+        }
+    }
+    result
+}
+
+fn transform_point(m: &Matrix, p: [f32; 3]) -> [f32; 3] {
+    let mut result = [0.0; 3];
+    for (row, value) in result.iter_mut().enumerate() {
+        *value = m[0][row] * p[0] + m[1][row] * p[1] + m[2][row] * p[2] + m[3][row];
+    }
+    result
+}
+
+/// The cofactor matrix of the upper 3x3 part, which is its inverse transpose scaled by the
+/// determinant. Normals are renormalized after the transform, so only the determinant's sign matters.
+fn normal_matrix(m: &Matrix) -> [[f32; 3]; 3] {
+    let c = |col: usize, row: usize| {
+        let (c0, c1) = ((col + 1) % 3, (col + 2) % 3);
+        let (r0, r1) = ((row + 1) % 3, (row + 2) % 3);
+        m[c0][r0] * m[c1][r1] - m[c1][r0] * m[c0][r1]
+    };
+    let cofactor = [
+        [c(0, 0), c(0, 1), c(0, 2)],
+        [c(1, 0), c(1, 1), c(1, 2)],
+        [c(2, 0), c(2, 1), c(2, 2)],
+    ];
+    let determinant: f32 = (0..3).map(|row| m[0][row] * cofactor[0][row]).sum();
+    let sign = if determinant < 0.0 { -1.0 } else { 1.0 };
+    cofactor.map(|column| column.map(|value| value * sign))
+}
+
+fn transform_normal(m: &[[f32; 3]; 3], n: [f32; 3]) -> [f32; 3] {
+    let mut result = [0.0; 3];
+    for (row, value) in result.iter_mut().enumerate() {
+        *value = m[0][row] * n[0] + m[1][row] * n[1] + m[2][row] * n[2];
+    }
+    let length = result.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if length > 0.0 {
+        result.map(|v| v / length)
+    } else {
+        result
+    }
+}
+
+impl From<io::Error> for GltfError {
+    fn from(e: io::Error) -> Self {
+        GltfError::Io(e)
+    }
+}
+
+impl From<::gltf::Error> for GltfError {
+    fn from(e: ::gltf::Error) -> Self {
+        GltfError::Gltf(e)
+    }
+}
+
+impl From<serde_json::Error> for GltfError {
+    fn from(e: serde_json::Error) -> Self {
+        GltfError::Json(e)
+    }
+}
+
+impl From<AddMeshError> for GltfError {
+    fn from(e: AddMeshError) -> Self {
+        GltfError::AddMesh(e)
+    }
+}
+
+impl fmt::Display for GltfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GltfError::Io(e) => e.fmt(f),
+            GltfError::Gltf(e) => e.fmt(f),
+            GltfError::Json(e) => e.fmt(f),
+            GltfError::AddMesh(e) => e.fmt(f),
+            GltfError::Unsupported(message) => write!(f, "unsupported glTF: {message}"),
+            GltfError::Invalid(message) => write!(f, "invalid glTF: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for GltfError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GltfError::Io(e) => Some(e),
+            GltfError::Gltf(e) => Some(e),
+            GltfError::Json(e) => Some(e),
+            GltfError::AddMesh(e) => Some(e),
+            GltfError::Unsupported(_) | GltfError::Invalid(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Mesh, Vertex};
+    use std::borrow::Cow;
+
+    #[test]
+    fn strips_and_fans_become_triangle_lists() {
+        assert_eq!(
+            triangle_list(Mode::TriangleStrip, vec![0, 1, 2, 3, 4]),
+            [0, 1, 2, 1, 3, 2, 2, 3, 4]
+        );
+        // The repeated 3 and 5 join two strips with degenerate triangles.
+        assert_eq!(
+            triangle_list(Mode::TriangleStrip, vec![0, 1, 2, 3, 3, 5, 5, 6, 7]),
+            [0, 1, 2, 1, 3, 2, 5, 6, 7]
+        );
+        assert_eq!(
+            triangle_list(Mode::TriangleFan, vec![0, 1, 2, 3]),
+            [1, 2, 0, 2, 3, 0]
+        );
+        assert!(triangle_list(Mode::TriangleFan, vec![0, 1]).is_empty());
+    }
+
+    /// A quad drawn as a triangle strip, without indices, with `nodes` and `scenes` as given.
+    fn quad(nodes_and_scenes: &str) -> String {
+        format!(
+            r#"{{
+                "asset": {{ "version": "2.0" }},
+                "buffers": [{{
+                    "byteLength": 48,
+                    "uri": "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAAAACAPwAAgD8AAAAA"
+                }}],
+                "bufferViews": [{{ "buffer": 0, "byteLength": 48 }}],
+                "accessors": [{{
+                    "bufferView": 0,
+                    "componentType": 5126,
+                    "count": 4,
+                    "type": "VEC3",
+                    "min": [0, 0, 0],
+                    "max": [1, 1, 0]
+                }}],
+                "meshes": [{{ "primitives": [{{ "attributes": {{ "POSITION": 0 }}, "mode": 5 }}] }}]
+                {nodes_and_scenes}
+            }}"#
+        )
+    }
+
+    #[test]
+    fn loads_buffers_from_data_uris() {
+        let scene = GltfScene::from_slice(quad("").as_bytes(), None).unwrap();
+        let primitive = &scene.primitives()[0];
+        assert_eq!(
+            primitive.positions,
+            [
+                [0.0, 0.0, 0.0],
+                [1.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0],
+                [1.0, 1.0, 0.0]
+            ]
+        );
+        assert_eq!(primitive.indices, [0, 1, 2, 1, 3, 2]);
+        assert_eq!(primitive.mesh_decl().validate(), Ok(()));
+    }
+
+    #[test]
+    fn meshes_take_the_transform_of_their_node() {
+        let json = quad(
+            r#", "nodes": [{ "mesh": 0, "translation": [0, 0, 2] }],
+                "scenes": [{ "nodes": [0] }]"#,
+        );
+        let scene = GltfScene::from_slice(json.as_bytes(), None).unwrap();
+        assert_eq!(scene.primitives()[0].positions[3], [1.0, 1.0, 2.0]);
+    }
+
+    #[test]
+    fn meshes_instanced_by_several_nodes_are_rejected() {
+        let json = quad(
+            r#", "nodes": [{ "mesh": 0 }, { "mesh": 0, "translation": [2, 0, 0] }],
+                "scenes": [{ "nodes": [0, 1] }]"#,
+        );
+        match GltfScene::from_slice(json.as_bytes(), None) {
+            Err(GltfError::Unsupported(message)) => {
+                assert_eq!(message, "mesh 0 is instanced by nodes 0 and 1")
+            }
+            result => panic!("loaded a multiply instanced mesh: {:?}", result.map(|_| ())),
+        }
+    }
+
+    /// The quad's triangles unwrapped onto the atlases in `atlas_indices`.
+    fn output(atlas_indices: [i32; 2]) -> AtlasOutput {
+        let mut vertex_array = Vec::new();
+        for (triangle, &atlas_index) in [[0, 1, 2], [1, 3, 2]].iter().zip(&atlas_indices) {
+            for &xref in triangle {
+                vertex_array.push(Vertex {
+                    atlas_index,
+                    chart_index: atlas_index,
+                    uv: [0.0, 0.0],
+                    xref,
+                });
+            }
+        }
+        AtlasOutput {
+            width: 8,
+            height: 8,
+            atlas_count: atlas_indices.iter().max().map_or(0, |&max| max as u32 + 1),
+            chart_count: 2,
+            texels_per_unit: 1.0,
+            utilization: vec![0.0; 2],
+            image: None,
+            meshes: vec![Mesh {
+                index_array: Cow::Owned((0..6).collect()),
+                chart_array: Vec::new(),
+                vertex_array,
+            }],
+        }
+    }
+
+    /// Writes the quad with `output` and reads back its `_ATLAS_INDEX` attribute, if any.
+    fn atlas_indices(output: &AtlasOutput) -> Option<Vec<[f32; 1]>> {
+        let scene = GltfScene::from_slice(quad("").as_bytes(), None).unwrap();
+        let mut glb = Vec::new();
+        scene.write_glb(&mut glb, output).unwrap();
+
+        let written = GltfScene::from_slice(&glb, None).unwrap();
+        let accessor =
+            written.json["meshes"][0]["primitives"][0]["attributes"]["_ATLAS_INDEX"].as_u64()?;
+        let accessor = written.document.accessors().nth(accessor as usize).unwrap();
+        Some(written.read_floats::<1>(&accessor).unwrap())
+    }
+
+    #[test]
+    fn write_glb_stores_the_atlas_of_every_vertex_when_there_are_several() {
+        assert_eq!(atlas_indices(&output([0, 0])), None);
+        assert_eq!(
+            atlas_indices(&output([0, 1])),
+            Some(vec![[0.0], [0.0], [0.0], [1.0], [1.0], [1.0]])
+        );
+    }
+}
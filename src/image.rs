@@ -0,0 +1,290 @@
+//! Decoding of the atlas image created with `PackOptions::create_image`, and a dependency-free
+//! PNG writer for debug images of it.
+
+use crate::root::xatlas::{
+    kImageChartIndexMask, kImageHasChartIndexBit, kImageIsBilinearBit, kImageIsPaddingBit,
+};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Largest block of a stored (uncompressed) deflate stream.
+const MAX_STORED_BLOCK: usize = 0xFFFF;
+
+/// A decoded texel of the atlas image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AtlasTexel {
+    /// Index of the chart the texel belongs to, counted over all meshes.
+    /// `None` for texels no chart touches.
+    pub chart: Option<u32>,
+    /// The texel was added around a chart for `PackOptions::padding`.
+    pub is_padding: bool,
+    /// The texel was added around a chart for `PackOptions::bilinear`.
+    pub is_bilinear: bool,
+    /// The texel is covered by one of the chart's triangles.
+    pub is_triangle: bool,
+}
+
+/// One atlas of the image, `width * height` texels in row-major order.
+#[derive(Debug, Clone, Copy)]
+pub struct ImagePage<'a> {
+    pub width: u32,
+    pub height: u32,
+    pub texels: &'a [u32],
+}
+
+impl AtlasTexel {
+    pub fn decode(texel: u32) -> Self {
+        let chart = if texel & kImageHasChartIndexBit != 0 {
+            Some(texel & kImageChartIndexMask)
+        } else {
+            None
+        };
+        let is_padding = texel & kImageIsPaddingBit != 0;
+        let is_bilinear = texel & kImageIsBilinearBit != 0;
+        AtlasTexel {
+            chart,
+            is_padding,
+            is_bilinear,
+            is_triangle: chart.is_some() && !is_padding && !is_bilinear,
+        }
+    }
+}
+
+impl<'a> ImagePage<'a> {
+    /// Splits out page `atlas_index` of an image holding `atlas_count` pages.
+    pub(crate) fn new(image: &'a [u32], width: u32, height: u32, atlas_index: u32) -> Option<Self> {
+        let size = width as usize * height as usize;
+        let start = atlas_index as usize * size;
+        Some(ImagePage {
+            width,
+            height,
+            texels: image.get(start..start + size)?,
+        })
+    }
+
+    pub fn texel(&self, x: u32, y: u32) -> AtlasTexel {
+        AtlasTexel::decode(self.texels[y as usize * self.width as usize + x as usize])
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = AtlasTexel> + 'a {
+        self.texels.iter().map(|&texel| AtlasTexel::decode(texel))
+    }
+
+    /// Renders the page as 8-bit RGB: a color per chart, darker for padding and bilinear texels,
+    /// black where there is no chart, and white where a chart borders other texels.
+    pub fn to_rgb(&self) -> Vec<u8> {
+        let mut rgb = Vec::with_capacity(self.texels.len() * 3);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let texel = self.texel(x, y);
+                let color = match texel.chart {
+                    None => [0; 3],
+                    Some(_) if self.is_outline(x, y, texel) => [255; 3],
+                    Some(chart) if texel.is_triangle => chart_color(chart),
+                    Some(chart) if texel.is_padding => chart_color(chart).map(|c| c / 3),
+                    Some(chart) => chart_color(chart).map(|c| c / 3 * 2),
+                };
+                rgb.extend_from_slice(&color);
+            }
+        }
+        rgb
+    }
+
+    /// Writes the page as rendered by `to_rgb` as PNG.
+    pub fn write_png(&self, mut writer: impl Write) -> io::Result<()> {
+        let rgb = self.to_rgb();
+        let row_size = self.width as usize * 3;
+
+        // Every row starts with filter type 0 (none).
+        let mut raw = Vec::with_capacity((row_size + 1) * self.height as usize);
+        for row in rgb.chunks_exact(row_size.max(1)) {
+            raw.push(0);
+            raw.extend_from_slice(row);
+        }
+
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&self.width.to_be_bytes());
+        header.extend_from_slice(&self.height.to_be_bytes());
+        // 8 bits per channel, RGB, deflate, adaptive filtering, no interlacing.
+        header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+        writer.write_all(b"\x89PNG\r\n\x1a\n")?;
+        write_chunk(&mut writer, b"IHDR", &header)?;
+        write_chunk(&mut writer, b"IDAT", &zlib_stored(&raw))?;
+        write_chunk(&mut writer, b"IEND", &[])?;
+        writer.flush()
+    }
+
+    pub fn save_png(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.write_png(BufWriter::new(File::create(path)?))
+    }
+
+    /// A chart texel is on the outline if one of its 4 neighbors belongs to another chart or
+    /// to none.
+    fn is_outline(&self, x: u32, y: u32, texel: AtlasTexel) -> bool {
+        if !texel.is_triangle {
+            return false;
+        }
+        let neighbors = [
+            (x.wrapping_sub(1), y),
+            (x + 1, y),
+            (x, y.wrapping_sub(1)),
+            (x, y + 1),
+        ];
+        neighbors.iter().any(|&(nx, ny)| {
+            nx >= self.width
+                || ny >= self.height
+                || !self.texel(nx, ny).is_triangle
+                || self.texel(nx, ny).chart != texel.chart
+        })
+    }
+}
+
+/// A bright color that differs between neighboring chart indices.
+pub(crate) fn chart_color(chart: u32) -> [u8; 3] {
+    let hash = chart.wrapping_mul(0x9E37_79B9);
+    [
+        128 | (hash >> 24) as u8,
+        128 | (hash >> 16) as u8,
+        128 | (hash >> 8) as u8,
+    ]
+}
+
+fn write_chunk(writer: &mut impl Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    writer.write_all(kind)?;
+    writer.write_all(data)?;
+    let crc = crc32(crc32(!0, kind), data);
+    writer.write_all(&(!crc).to_be_bytes())
+}
+
+/// Wraps the data in a zlib stream of stored deflate blocks. Debug images don't need to be
+/// small, and this avoids a compression dependency.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let block_count = data.len().div_ceil(MAX_STORED_BLOCK).max(1);
+    let mut out = Vec::with_capacity(data.len() + block_count * 5 + 6);
+    out.extend_from_slice(&[0x78, 0x01]);
+
+    let mut blocks = data.chunks(MAX_STORED_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let is_final = blocks.peek().is_none();
+        let length = block.len() as u16;
+        out.push(is_final as u8);
+        out.extend_from_slice(&length.to_le_bytes());
+        out.extend_from_slice(&(!length).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32(mut crc: u32, data: &[u8]) -> u32 {
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    // 5552 is the largest number of bytes that can be summed before b can overflow.
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    #[test]
+    fn crc32_of_the_iend_chunk() {
+        assert_eq!(!crc32(!0, b"IEND"), 0xAE42_6082);
+    }
+
+    #[test]
+    fn adler32_of_a_known_string() {
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+        assert_eq!(adler32(&[]), 1);
+    }
+
+    #[test]
+    fn decode_reads_the_chart_index() {
+        let texel = AtlasTexel::decode(kImageHasChartIndexBit | 42);
+        assert_eq!(texel.chart, Some(42));
+        assert!(texel.is_triangle);
+        assert_eq!(AtlasTexel::decode(42).chart, None);
+    }
+
+    #[test]
+    fn decode_reads_the_bilinear_bit() {
+        let texel = AtlasTexel::decode(kImageHasChartIndexBit | kImageIsBilinearBit | 7);
+        assert_eq!(texel.chart, Some(7));
+        assert!(texel.is_bilinear);
+        assert!(!texel.is_padding);
+        assert!(!texel.is_triangle);
+    }
+
+    #[test]
+    fn decode_reads_the_padding_bit() {
+        let texel = AtlasTexel::decode(kImageHasChartIndexBit | kImageIsPaddingBit | 7);
+        assert_eq!(texel.chart, Some(7));
+        assert!(texel.is_padding);
+        assert!(!texel.is_bilinear);
+        assert!(!texel.is_triangle);
+    }
+
+    #[test]
+    fn a_2x2_page_round_trips_through_png() {
+        // Chart 0 covers the top row, chart 1 pads the bottom left texel.
+        let texels = [
+            kImageHasChartIndexBit,
+            kImageHasChartIndexBit,
+            kImageHasChartIndexBit | kImageIsPaddingBit | 1,
+            0,
+        ];
+        let page = ImagePage::new(&texels, 2, 2, 0).unwrap();
+        let rgb = page.to_rgb();
+        let padding = chart_color(1).map(|c| c / 3);
+        assert_eq!(
+            rgb,
+            [[255; 3], [255; 3], padding, [0; 3]].concat(),
+            "the top row borders other texels, so it is outlined"
+        );
+
+        let mut png = Vec::new();
+        page.write_png(&mut png).unwrap();
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(&png[16..24], [0, 0, 0, 2, 0, 0, 0, 2]);
+        assert!(png.ends_with(&[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]));
+
+        // The IDAT chunk holds a single stored block of the filtered rows.
+        let idat = 8 + 25;
+        let length = u32::from_be_bytes(png[idat..idat + 4].try_into().unwrap()) as usize;
+        assert_eq!(&png[idat + 4..idat + 8], b"IDAT");
+        let zlib = &png[idat + 8..idat + 8 + length];
+        let raw = &zlib[7..zlib.len() - 4];
+        let rows: Vec<u8> = raw.chunks(7).flat_map(|row| row[1..].to_vec()).collect();
+        assert_eq!(rows, rgb);
+        assert_eq!(zlib[zlib.len() - 4..], adler32(raw).to_be_bytes());
+    }
+}
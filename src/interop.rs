@@ -0,0 +1,123 @@
+//! Conversions from slices of plain arrays and of the vector types of other math crates into
+//! `MeshData` and `IndexData`.
+
+use crate::{IndexData, MeshData};
+
+impl<'a> From<&'a [f32]> for MeshData<'a> {
+    fn from(data: &'a [f32]) -> Self {
+        MeshData::Contiguous(data)
+    }
+}
+
+impl<'a> From<&'a [[f32; 2]]> for MeshData<'a> {
+    fn from(data: &'a [[f32; 2]]) -> Self {
+        MeshData::Vec2(data)
+    }
+}
+
+impl<'a> From<&'a [[f32; 3]]> for MeshData<'a> {
+    fn from(data: &'a [[f32; 3]]) -> Self {
+        MeshData::Vec3(data)
+    }
+}
+
+impl<'a> From<&'a [u16]> for IndexData<'a> {
+    fn from(data: &'a [u16]) -> Self {
+        IndexData::U16(data)
+    }
+}
+
+impl<'a> From<&'a [u32]> for IndexData<'a> {
+    fn from(data: &'a [u32]) -> Self {
+        IndexData::U32(data)
+    }
+}
+
+#[cfg(feature = "bytemuck")]
+impl<'a> MeshData<'a> {
+    /// Reads an f32 attribute of `components` components at byte `offset` of every element of
+    /// `vertices`, e.g. positions with
+    /// `MeshData::from_pod(&vertices, std::mem::offset_of!(MyVertex, position), 3)`.
+    ///
+    /// # Panics
+    ///
+    /// If the attribute doesn't fit in `T`.
+    pub fn from_pod<T: bytemuck::Pod>(vertices: &'a [T], offset: usize, components: usize) -> Self {
+        let stride = std::mem::size_of::<T>();
+        assert!(
+            offset + components * std::mem::size_of::<f32>() <= stride,
+            "an attribute of {} f32 components at offset {} doesn't fit in {} bytes",
+            components,
+            offset,
+            stride
+        );
+        let data: &[u8] = bytemuck::cast_slice(vertices);
+        MeshData::WithStride {
+            data: data.get(offset..).unwrap_or(&[]),
+            stride: stride as u32,
+        }
+    }
+}
+
+/// Implements `From` for slices of vector types that are laid out like `[f32; N]`.
+#[cfg(any(feature = "glam", feature = "mint", feature = "nalgebra"))]
+macro_rules! impl_from_vectors {
+    ($variant:ident, $components:expr, $($vector:ty),+) => {
+        $(
+            impl<'a> From<&'a [$vector]> for MeshData<'a> {
+                fn from(data: &'a [$vector]) -> Self {
+                    MeshData::$variant(unsafe { cast_slice::<$vector, $components>(data) })
+                }
+            }
+        )+
+    };
+}
+
+#[cfg(feature = "glam")]
+impl_from_vectors!(Vec2, 2, glam::Vec2);
+#[cfg(feature = "glam")]
+impl_from_vectors!(Vec3, 3, glam::Vec3);
+
+#[cfg(feature = "mint")]
+impl_from_vectors!(Vec2, 2, mint::Point2<f32>, mint::Vector2<f32>);
+#[cfg(feature = "mint")]
+impl_from_vectors!(Vec3, 3, mint::Point3<f32>, mint::Vector3<f32>);
+
+#[cfg(feature = "nalgebra")]
+impl_from_vectors!(Vec2, 2, nalgebra::Point2<f32>, nalgebra::Vector2<f32>);
+#[cfg(feature = "nalgebra")]
+impl_from_vectors!(Vec3, 3, nalgebra::Point3<f32>, nalgebra::Vector3<f32>);
+
+/// Reinterprets a slice of vectors as arrays.
+///
+/// # Safety
+///
+/// `T` has to consist of exactly `N` f32 components without padding, e.g. be `#[repr(C)]`
+/// with `N` f32 fields. Size and alignment are checked, the field layout isn't.
+#[cfg(any(feature = "glam", feature = "mint", feature = "nalgebra"))]
+unsafe fn cast_slice<T, const N: usize>(data: &[T]) -> &[[f32; N]] {
+    assert_eq!(std::mem::size_of::<T>(), std::mem::size_of::<[f32; N]>());
+    assert_eq!(std::mem::align_of::<T>(), std::mem::align_of::<f32>());
+    std::slice::from_raw_parts(data.as_ptr() as *const [f32; N], data.len())
+}
+
+#[cfg(all(test, feature = "bytemuck"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_pod_reads_the_attribute_of_every_vertex() {
+        // A UV followed by a position.
+        let vertices = [[0.0, 0.0, 1.0, 2.0, 3.0], [0.0, 0.0, 4.0, 5.0, 6.0f32]];
+        let data = MeshData::from_pod(&vertices, 8, 3);
+        assert_eq!(data.count(3), 2);
+        assert_eq!(data.read(1, 3), [4.0, 5.0, 6.0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "doesn't fit")]
+    fn from_pod_rejects_an_attribute_past_the_end_of_the_vertex() {
+        let vertices = [[0.0f32; 5]; 2];
+        MeshData::from_pod(&vertices, 12, 3);
+    }
+}
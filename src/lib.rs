@@ -2,20 +2,94 @@ include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
 
 use crate::root::xatlas;
 use crate::root::xatlas::{IndexFormat_UInt16, IndexFormat_UInt32, ParameterizeFunc};
-use std::marker::PhantomData;
-use std::pin::Pin;
-use std::{mem, slice};
-
-pub struct Xatlas<'x> {
+use std::borrow::Cow;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use std::{fmt, slice};
+
+mod attribute;
+mod density;
+#[cfg(feature = "gltf")]
+pub mod gltf;
+mod image;
+mod interop;
+#[cfg(any(feature = "obj", feature = "ply", feature = "stl"))]
+mod load;
+#[macro_use]
+mod logging;
+mod memory;
+mod metrics;
+#[cfg(feature = "obj")]
+pub mod obj;
+mod parameterize;
+mod pipeline;
+#[cfg(feature = "ply")]
+pub mod ply;
+mod progress;
+mod source;
+#[cfg(feature = "stl")]
+pub mod stl;
+mod svg;
+mod validate;
+
+pub use crate::attribute::{Attr, AttrFormat, Dequantize, VertexLayout};
+pub use crate::density::{ChartDensity, DensityReport, MeshDensity};
+pub use crate::image::{AtlasTexel, ImagePage};
+#[cfg(any(feature = "obj", feature = "ply", feature = "stl"))]
+pub use crate::load::LoadError;
+pub use crate::memory::{memory_stats, use_rust_allocator, AllocatorInUse, MemoryStats};
+pub use crate::metrics::{
+    AtlasMetrics, ChartMetrics, DistortionMetrics, MeshMetrics, TriangleMetrics,
+};
+pub use crate::parameterize::Parameterizer;
+pub use crate::pipeline::{
+    AtlasBuilder, ChartedAtlas, Interrupted, MeshInput, PackedAtlas, UvMeshInput,
+};
+use crate::progress::Progress;
+pub use crate::progress::{CancellationToken, Cancelled};
+pub use crate::source::MeshSource;
+pub use crate::svg::SvgOptions;
+pub use crate::validate::{
+    ChartOutOfBounds, ChartRef, FaceRef, LayoutReport, TexelOverlap, TriangleOverlap,
+};
+
+/// Direct wrapper around an xatlas atlas. It is up to the caller to call its methods in the
+/// order xatlas expects; `AtlasBuilder` enforces that order at compile time instead.
+pub struct Xatlas {
     handle: *mut xatlas::Atlas,
-    progress_callback: Option<ProgressCallbackPointer>,
-    phantom: PhantomData<&'x ()>,
+    progress: Box<Mutex<Progress>>,
+    /// Charged with xatlas's allocations, which point to it until `drop` destroys the atlas.
+    memory: Arc<memory::Usage>,
 }
 
+/// Vertex attribute data. Positions and normals have 3 components, UVs have 2.
 #[derive(Debug)]
 pub enum MeshData<'a> {
+    /// Tightly packed floats, the number of components depends on the attribute.
     Contiguous(&'a [f32]),
+    /// f32 components at the start of every `stride` bytes. Data or strides that aren't 4 byte
+    /// aligned are copied into an aligned buffer before they are passed to xatlas.
     WithStride { data: &'a [u8], stride: u32 },
+    /// Two component data, e.g. UVs.
+    Vec2(&'a [[f32; 2]]),
+    /// Three component data, e.g. positions and normals.
+    Vec3(&'a [[f32; 3]]),
+    /// Strided data in a format other than f32, such as half floats or quantized positions.
+    /// It is decoded and dequantized into a temporary f32 buffer before it is passed to xatlas.
+    Encoded {
+        data: &'a [u8],
+        stride: u32,
+        format: AttrFormat,
+        dequantize: Dequantize,
+    },
+}
+
+/// Identifies one of the vertex attribute streams of a `MeshDecl` or `UvMeshDecl`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VertexStream {
+    Position,
+    Normal,
+    Uv,
 }
 
 #[derive(Debug)]
@@ -31,7 +105,10 @@ pub struct MeshDecl<'a> {
     /// The input UVs are provided as a hint to the chart generator.
     pub vertex_uv_data: Option<MeshData<'a>>,
 
-    pub face_ignore_data: Option<bool>,
+    /// Must be faceCount in length.
+    /// Don't atlas faces set to true. Ignored faces still exist in the output meshes,
+    /// their vertices get an atlas_index of -1 and a uv of (0, 0). See `Vertex::is_ignored`.
+    pub face_ignore_data: Option<&'a [bool]>,
     /// Must be faceCount in length.
     /// Only faces with the same material will be assigned to the same chart.
     pub face_material_data: Option<&'a [u32]>,
@@ -54,21 +131,77 @@ pub struct UvMeshDecl<'a> {
     pub index_data: Option<IndexData<'a>>,
 }
 
-#[derive(Debug)]
+/// Errors returned by `Xatlas::add_mesh`, `Xatlas::add_uv_mesh` and the `validate` methods of
+/// the mesh declarations. Most of them are detected on the Rust side before anything is passed to xatlas.
+#[derive(Debug, Clone, PartialEq)]
 pub enum AddMeshError {
-    /// Unspecified error.
-    Error,
+    /// xatlas rejected the mesh. `code` is xatlas's `AddMeshError` value; the errors it stands
+    /// for are normally caught with more detail by the `validate` methods.
+    Error { code: i32 },
     /// An index is >= MeshDecl vertexCount.
-    IndexOutOfRange,
+    IndexOutOfRange {
+        face: u32,
+        corner: u32,
+        index: u32,
+        vertex_count: u32,
+    },
     /// Must be >= 3.
-    InvalidFaceVertexCount,
+    InvalidFaceVertexCount { face: u32, count: u8 },
     /// Not evenly divisible by 3 - expecting triangles.
-    InvalidIndexCount,
+    InvalidIndexCount { index_count: u32 },
+    /// The face vertex counts don't add up to the number of indices.
+    CornerCountMismatch { expected: u32, actual: u64 },
+    /// MeshDecl::face_vertex_count must be faceCount in length.
+    FaceVertexCountLengthMismatch { expected: u32, actual: usize },
+    /// MeshDecl::face_ignore_data must be faceCount in length.
+    FaceIgnoreLengthMismatch { expected: u32, actual: usize },
+    /// face_material_data must be faceCount in length.
+    MaterialLengthMismatch { expected: u32, actual: usize },
+    /// A typed MeshData variant has the wrong number of components for the stream, e.g. `Vec2` positions.
+    ComponentMismatch {
+        stream: VertexStream,
+        expected: u32,
+        actual: u32,
+    },
+    /// Contiguous data isn't a multiple of the stream's component count.
+    InvalidDataLength {
+        stream: VertexStream,
+        len: usize,
+        components: u32,
+    },
+    /// A MeshData::WithStride stride is smaller than one element of the stream.
+    StrideTooSmall {
+        stream: VertexStream,
+        stride: u32,
+        min: u32,
+    },
+    /// Normal and UV streams must describe as many vertices as the position stream.
+    VertexCountMismatch {
+        stream: VertexStream,
+        expected: u32,
+        actual: u32,
+    },
+    /// A vertex position contains a NaN or infinite component.
+    NonFinitePosition { vertex: u32 },
+    /// An attribute of a `VertexLayout` extends past the end of the vertex.
+    AttributeOutsideStride {
+        stream: VertexStream,
+        end: usize,
+        stride: u32,
+    },
+    /// The buffer given to `VertexLayout::mesh_decl` isn't a whole number of vertices long.
+    BufferLengthMismatch { len: usize, stride: u32 },
+    /// A face of a `MeshSource` has more than the 255 vertices xatlas supports.
+    TooManyFaceVertices { face: u32, count: usize },
 }
 
+#[derive(Clone)]
 pub struct ChartOptions {
     pub param_func: ParameterizeFunc,
 
+    /// Rust replacement for param_func, takes precedence over it. See `with_parameterizer`.
+    pub parameterizer: Option<Parameterizer>,
+
     /// Don't grow charts to be larger than this. 0 means no limit.
     pub max_chart_area: f32,
 
@@ -140,14 +273,16 @@ pub enum ProgressCategory {
     BuildOutputMeshes,
 }
 
-#[derive(Debug)]
+/// Output mesh. Borrows from the `Xatlas` it was read from, unless it was obtained
+/// through `AtlasOutput` in which case it owns all of its data.
+#[derive(Debug, Clone)]
 pub struct Mesh<'a> {
-    pub index_array: &'a [u32],
+    pub index_array: Cow<'a, [u32]>,
     pub chart_array: Vec<Chart<'a>>,
     pub vertex_array: Vec<Vertex>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Vertex {
     pub atlas_index: i32,
     pub chart_index: i32,
@@ -155,15 +290,28 @@ pub struct Vertex {
     pub xref: u32,
 }
 
-#[derive(Debug)]
+impl Vertex {
+    /// True if this vertex only belongs to faces that were excluded through
+    /// `MeshDecl::face_ignore_data`, in which case it wasn't assigned to any atlas.
+    pub fn is_ignored(&self) -> bool {
+        self.atlas_index < 0
+    }
+
+    /// The UV as another vector type, e.g. `glam::Vec2` or `mint::Point2<f32>`.
+    pub fn uv_as<T: From<[f32; 2]>>(&self) -> T {
+        T::from(self.uv)
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Chart<'a> {
-    pub face_array: &'a [u32],
+    pub face_array: Cow<'a, [u32]>,
     pub atlas_index: u32,
     pub type_: ChartType,
     pub material: u32,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ChartType {
     Planar,
     Ortho,
@@ -172,12 +320,39 @@ pub enum ChartType {
     Invalid,
 }
 
-impl<'x> Xatlas<'x> {
+/// Owned copy of everything `Xatlas` produced. Unlike `Xatlas::meshes` it doesn't borrow
+/// from the atlas, so it can be kept around after the atlas is dropped or sent to other threads.
+#[derive(Debug, Clone)]
+pub struct AtlasOutput {
+    /// Atlas width in texels.
+    pub width: u32,
+    /// Atlas height in texels.
+    pub height: u32,
+    /// Number of sub-atlases.
+    pub atlas_count: u32,
+    /// Total number of charts in all meshes.
+    pub chart_count: u32,
+    pub texels_per_unit: f32,
+    /// Normalized atlas texel utilization, atlas_count in length.
+    pub utilization: Vec<f32>,
+    /// Only present if PackOptions::create_image was set.
+    pub image: Option<Vec<u32>>,
+    /// The output meshes, one per add_mesh call and in the same order. Functions that also read
+    /// the input geometry take it as `inputs: &[MeshDecl]`, the declarations in the order they
+    /// were added, so `inputs[i]` is the input of `meshes[i]` and `Vertex::xref` indexes its
+    /// vertices.
+    pub meshes: Vec<Mesh<'static>>,
+}
+
+impl Xatlas {
     pub fn new() -> Self {
+        logging::install_print();
+        memory::lock_allocator();
+
         Self {
             handle: unsafe { xatlas::Create() },
-            progress_callback: None,
-            phantom: PhantomData,
+            progress: Box::default(),
+            memory: Arc::default(),
         }
     }
 
@@ -205,7 +380,7 @@ impl<'x> Xatlas<'x> {
         unsafe { *self.handle }.texelsPerUnit
     }
 
-    pub fn utilization(&'x self) -> Option<&'x [f32]> {
+    pub fn utilization(&self) -> Option<&[f32]> {
         unsafe {
             if (*self.handle).utilization.is_null() {
                 None
@@ -218,7 +393,7 @@ impl<'x> Xatlas<'x> {
         }
     }
 
-    pub fn image(&'x self) -> Option<&'x [u32]> {
+    pub fn image(&self) -> Option<&[u32]> {
         unsafe {
             if (*self.handle).image.is_null() {
                 None
@@ -232,43 +407,46 @@ impl<'x> Xatlas<'x> {
         }
     }
 
-    pub fn meshes(&'x self) -> Vec<Mesh<'x>> {
-        unsafe { slice::from_raw_parts((*self.handle).meshes, (*self.handle).meshCount as usize) }
+    /// The texels of atlas `atlas_index`, or `None` if there is no image or no such atlas.
+    pub fn image_page(&self, atlas_index: u32) -> Option<ImagePage<'_>> {
+        ImagePage::new(self.image()?, self.width(), self.height(), atlas_index)
+    }
+
+    pub fn meshes(&self) -> Vec<Mesh<'_>> {
+        unsafe { raw_slice((*self.handle).meshes, (*self.handle).meshCount) }
             .iter()
             .map(|mesh| {
-                let chart_array =
-                    unsafe { slice::from_raw_parts(mesh.chartArray, mesh.chartCount as usize) }
-                        .iter()
-                        .map(|chart| Chart {
-                            face_array: unsafe {
-                                slice::from_raw_parts(chart.faceArray, chart.faceCount as usize)
-                            },
-                            atlas_index: chart.atlasIndex,
-                            type_: match chart.type_ {
-                                xatlas::ChartType_Planar => ChartType::Planar,
-                                xatlas::ChartType_Ortho => ChartType::Ortho,
-                                xatlas::ChartType_LSCM => ChartType::LSCM,
-                                xatlas::ChartType_Piecewise => ChartType::Piecewise,
-                                xatlas::ChartType_Invalid => ChartType::Invalid,
-                                _ => unreachable!(),
-                            },
-                            material: chart.material,
-                        })
-                        .collect();
-
-                let vertex_array =
-                    unsafe { slice::from_raw_parts(mesh.vertexArray, mesh.vertexCount as usize) }
-                        .iter()
-                        .map(|vertex| Vertex {
-                            atlas_index: vertex.atlasIndex,
-                            chart_index: vertex.chartIndex,
-                            uv: vertex.uv,
-                            xref: vertex.xref,
-                        })
-                        .collect();
+                let chart_array = unsafe { raw_slice(mesh.chartArray, mesh.chartCount) }
+                    .iter()
+                    .map(|chart| Chart {
+                        face_array: Cow::Borrowed(unsafe {
+                            raw_slice(chart.faceArray, chart.faceCount)
+                        }),
+                        atlas_index: chart.atlasIndex,
+                        type_: match chart.type_ {
+                            xatlas::ChartType_Planar => ChartType::Planar,
+                            xatlas::ChartType_Ortho => ChartType::Ortho,
+                            xatlas::ChartType_LSCM => ChartType::LSCM,
+                            xatlas::ChartType_Piecewise => ChartType::Piecewise,
+                            xatlas::ChartType_Invalid => ChartType::Invalid,
+                            _ => unreachable!(),
+                        },
+                        material: chart.material,
+                    })
+                    .collect();
+
+                let vertex_array = unsafe { raw_slice(mesh.vertexArray, mesh.vertexCount) }
+                    .iter()
+                    .map(|vertex| Vertex {
+                        atlas_index: vertex.atlasIndex,
+                        chart_index: vertex.chartIndex,
+                        uv: vertex.uv,
+                        xref: vertex.xref,
+                    })
+                    .collect();
 
                 let index_array =
-                    unsafe { slice::from_raw_parts(mesh.indexArray, mesh.indexCount as usize) };
+                    Cow::Borrowed(unsafe { raw_slice(mesh.indexArray, mesh.indexCount) });
 
                 Mesh {
                     index_array,
@@ -279,32 +457,81 @@ impl<'x> Xatlas<'x> {
             .collect()
     }
 
-    pub fn add_mesh(&mut self, mesh_decl: &'x MeshDecl<'x>) -> Result<(), AddMeshError> {
+    /// Draws the UV layout of atlas `atlas_index` as SVG, see `SvgOptions`.
+    pub fn to_svg(&self, atlas_index: u32) -> String {
+        self.to_svg_with_options(atlas_index, &SvgOptions::default())
+    }
+
+    pub fn to_svg_with_options(&self, atlas_index: u32, options: &SvgOptions) -> String {
+        svg::render(
+            &self.meshes(),
+            self.width(),
+            self.height(),
+            atlas_index,
+            options,
+        )
+    }
+
+    /// Copies the current results into an `AtlasOutput` that doesn't borrow from this atlas.
+    pub fn to_output(&self) -> AtlasOutput {
+        AtlasOutput {
+            width: self.width(),
+            height: self.height(),
+            atlas_count: self.atlas_count(),
+            chart_count: self.chart_count(),
+            texels_per_unit: self.texels_per_unit(),
+            utilization: self.utilization().map_or_else(Vec::new, <[f32]>::to_vec),
+            image: self.image().map(<[u32]>::to_vec),
+            meshes: self.meshes().into_iter().map(Mesh::into_owned).collect(),
+        }
+    }
+
+    /// Like `to_output`, but also destroys the atlas.
+    pub fn into_output(self) -> AtlasOutput {
+        self.to_output()
+    }
+
+    /// Add a mesh to the atlas. MeshDecl data is copied, so it can be freed after add_mesh returns.
+    pub fn add_mesh(&mut self, mesh_decl: &MeshDecl<'_>) -> Result<(), AddMeshError> {
         self.add_mesh_with_mesh_count_hint(mesh_decl, 0)
     }
 
     pub fn add_mesh_with_mesh_count_hint(
         &mut self,
-        mesh_decl: &'x MeshDecl<'x>,
+        mesh_decl: &MeshDecl<'_>,
         mesh_count_hint: u32,
     ) -> Result<(), AddMeshError> {
+        let _span = operation_span!("add_mesh", self.mesh_count());
+        mesh_decl.validate()?;
+
+        let vertex_count = mesh_decl.vertex_position_data.count(3);
+        let face_count = mesh_decl.resolved_face_count();
+
+        let mut buffers: [Vec<f32>; 3] = Default::default();
+        let [position_buffer, normal_buffer, uv_buffer] = &mut buffers;
+        let (position_data, position_stride) = mesh_decl
+            .vertex_position_data
+            .float_data(3, position_buffer);
+        let normal = mesh_decl
+            .vertex_normal_data
+            .as_ref()
+            .map(|d| d.float_data(3, normal_buffer));
+        let uv = mesh_decl
+            .vertex_uv_data
+            .as_ref()
+            .map(|d| d.float_data(2, uv_buffer));
+
         let decl = xatlas::MeshDecl {
-            vertexPositionData: mesh_decl.vertex_position_data.as_ptr() as _,
-            vertexNormalData: match &mesh_decl.vertex_normal_data {
-                None => std::ptr::null(),
-                Some(d) => d.as_ptr() as _,
-            },
-            vertexUvData: match &mesh_decl.vertex_uv_data {
-                None => std::ptr::null(),
-                Some(d) => d.as_ptr() as _,
-            },
+            vertexPositionData: position_data as _,
+            vertexNormalData: normal.map_or(std::ptr::null(), |(data, _)| data as _),
+            vertexUvData: uv.map_or(std::ptr::null(), |(data, _)| data as _),
             indexData: match &mesh_decl.index_data {
                 None => std::ptr::null(),
                 Some(d) => d.as_ptr() as _,
             },
             faceIgnoreData: match mesh_decl.face_ignore_data {
                 None => std::ptr::null(),
-                Some(ref d) => d,
+                Some(d) => d.as_ptr(),
             },
             faceMaterialData: match mesh_decl.face_material_data {
                 None => std::ptr::null(),
@@ -314,16 +541,13 @@ impl<'x> Xatlas<'x> {
                 None => std::ptr::null(),
                 Some(d) => d.as_ptr(),
             },
-            vertexCount: mesh_decl.vertex_position_data.count(),
-            vertexPositionStride: mesh_decl.vertex_position_data.stride(3),
-            vertexNormalStride: mesh_decl
-                .vertex_normal_data
-                .as_ref()
-                .map_or(0, |c| c.stride(3)),
-            vertexUvStride: mesh_decl.vertex_uv_data.as_ref().map_or(0, |d| d.stride(2)),
+            vertexCount: vertex_count,
+            vertexPositionStride: position_stride,
+            vertexNormalStride: normal.map_or(0, |(_, stride)| stride),
+            vertexUvStride: uv.map_or(0, |(_, stride)| stride),
             indexCount: mesh_decl.index_data.as_ref().map_or(0, |d| d.count()),
             indexOffset: 0,
-            faceCount: mesh_decl.face_count,
+            faceCount: face_count,
             indexFormat: mesh_decl
                 .index_data
                 .as_ref()
@@ -334,21 +558,42 @@ impl<'x> Xatlas<'x> {
             epsilon: mesh_decl.epsilon,
         };
 
-        let result = unsafe { xatlas::AddMesh(self.handle, &decl, mesh_count_hint) };
+        let result = {
+            let _operation = self.memory.begin();
+            unsafe { xatlas::AddMesh(self.handle, &decl, mesh_count_hint) }
+        };
 
         add_mesh_error_result(result)
     }
 
+    /// Adds a mesh that is read through the `MeshSource` trait. Its vertices and faces are
+    /// gathered into temporary buffers, which are copied by xatlas like `add_mesh` data.
+    pub fn add_mesh_source<S: MeshSource + ?Sized>(
+        &mut self,
+        source: &S,
+    ) -> Result<(), AddMeshError> {
+        let gathered = source::Gathered::new(source)?;
+        self.add_mesh(&gathered.mesh_decl())
+    }
+
     pub fn add_mesh_join(&mut self) {
+        let _operation = self.memory.begin();
         unsafe { xatlas::AddMeshJoin(self.handle) }
     }
 
-    pub fn add_uv_mesh(&mut self, decl: &UvMeshDecl<'x>) -> Result<(), AddMeshError> {
+    pub fn add_uv_mesh(&mut self, decl: &UvMeshDecl<'_>) -> Result<(), AddMeshError> {
+        let _span = operation_span!("add_uv_mesh", self.mesh_count());
+        decl.validate()?;
+
+        let vertex_count = decl.vertex_uv_data.as_ref().map_or(0, |d| d.count(2));
+        let mut uv_buffer = Vec::new();
+        let uv = decl
+            .vertex_uv_data
+            .as_ref()
+            .map(|d| d.float_data(2, &mut uv_buffer));
+
         let decl = xatlas::UvMeshDecl {
-            vertexUvData: match &decl.vertex_uv_data {
-                None => std::ptr::null(),
-                Some(d) => d.as_ptr() as _,
-            },
+            vertexUvData: uv.map_or(std::ptr::null(), |(data, _)| data as _),
             indexData: match &decl.index_data {
                 None => std::ptr::null(),
                 Some(d) => d.as_ptr() as _,
@@ -357,14 +602,8 @@ impl<'x> Xatlas<'x> {
                 None => std::ptr::null(),
                 Some(d) => d.as_ptr(),
             },
-            vertexCount: match &decl.vertex_uv_data {
-                None => 0,
-                Some(d) => d.count(),
-            },
-            vertexStride: match &decl.vertex_uv_data {
-                None => 0,
-                Some(d) => d.stride(2),
-            },
+            vertexCount: vertex_count,
+            vertexStride: uv.map_or(0, |(_, stride)| stride),
             indexCount: match &decl.index_data {
                 None => 0,
                 Some(d) => d.count(),
@@ -378,85 +617,328 @@ impl<'x> Xatlas<'x> {
                 },
             },
         };
-        let result = unsafe { xatlas::AddUvMesh(self.handle, &decl) };
+        let result = {
+            let _operation = self.memory.begin();
+            unsafe { xatlas::AddUvMesh(self.handle, &decl) }
+        };
 
         add_mesh_error_result(result)
     }
 
     /// Call after all AddMesh calls. Can be called multiple times to recompute charts with different options.
-    pub fn compute_charts(&mut self, options: &ChartOptions) {
+    pub fn compute_charts(&mut self, options: &ChartOptions) -> Result<(), Cancelled> {
+        let _span = operation_span!("compute_charts", self.mesh_count());
+        let parameterizer = options.parameterizer.as_ref();
         let options = options.convert();
 
-        unsafe { xatlas::ComputeCharts(self.handle, options) }
+        self.cancellable(|handle| {
+            parameterize::scope(parameterizer, || unsafe {
+                xatlas::ComputeCharts(handle, options)
+            })
+        })
     }
 
     /// Call after ComputeCharts. Can be called multiple times to re-pack charts with different options.
-    pub fn pack_charts(&mut self, pack_options: &PackOptions) {
+    pub fn pack_charts(&mut self, pack_options: &PackOptions) -> Result<(), Cancelled> {
+        let _span = operation_span!("pack_charts", self.mesh_count());
         let pack_options = pack_options.convert();
 
-        unsafe { xatlas::PackCharts(self.handle, pack_options) }
+        self.cancellable(|handle| {
+            unsafe { xatlas::PackCharts(handle, pack_options) };
+            Ok(())
+        })
+    }
+
+    /// Re-packs the charts without `PackOptions::max_chart_size` if the texel density of any
+    /// chart differs from `texels_per_unit` by more than `tolerance`, see
+    /// `DensityReport::deviating`. The current `texels_per_unit` is kept, so the atlas grows
+    /// instead of charts being scaled down. Returns the density after re-packing, if needed.
+    /// `inputs` are paired with the output meshes as described on [`AtlasOutput::meshes`].
+    pub fn equalize_texel_density(
+        &mut self,
+        inputs: &[MeshDecl<'_>],
+        pack_options: &PackOptions,
+        tolerance: f32,
+    ) -> Result<DensityReport, Cancelled> {
+        let report = |atlas: &Self| {
+            DensityReport::from_meshes(inputs, &atlas.meshes(), atlas.texels_per_unit())
+        };
+        let before = report(self);
+        if before.is_uniform(tolerance) {
+            return Ok(before);
+        }
+        self.pack_charts(&PackOptions {
+            max_chart_size: 0,
+            texels_per_unit: self.texels_per_unit(),
+            ..*pack_options
+        })?;
+        Ok(report(self))
     }
 
     /// Equivalent to calling ComputeCharts and PackCharts in sequence. Can be called multiple times to regenerate with different options.
-    pub fn generate(&mut self, chart_options: &ChartOptions, pack_options: &PackOptions) {
+    pub fn generate(
+        &mut self,
+        chart_options: &ChartOptions,
+        pack_options: &PackOptions,
+    ) -> Result<(), Cancelled> {
+        let _span = operation_span!("generate", self.mesh_count());
+        let parameterizer = chart_options.parameterizer.as_ref();
         let chart_options = chart_options.convert();
         let pack_options = pack_options.convert();
 
-        unsafe { xatlas::Generate(self.handle, chart_options, pack_options) }
+        self.cancellable(|handle| {
+            parameterize::scope(parameterizer, || unsafe {
+                xatlas::Generate(handle, chart_options, pack_options)
+            })
+        })
     }
 
+    /// Called by xatlas as it makes progress, returning false cancels the current operation.
+    /// The callback may be called from xatlas's worker threads, but never concurrently.
+    /// If it panics, the operation is cancelled and returns `Cancelled::Panicked`.
     pub fn set_progress_callback(
         &mut self,
-        callback: impl Fn(ProgressCategory, i32) -> bool + 'static,
+        callback: impl FnMut(ProgressCategory, i32) -> bool + Send + 'static,
     ) {
-        let callback: ProgressCallbackPointer = Box::pin(Box::new(callback));
-        let user_data = &*callback as *const _ as *mut _;
-        self.progress_callback = Some(callback);
+        progress::lock(&self.progress).callback = Some(Box::new(callback));
+        self.sync_progress_callback();
+    }
 
-        unsafe { xatlas::SetProgressCallback(self.handle, Some(progress_callback), user_data) }
+    pub fn clear_progress_callback(&mut self) {
+        progress::lock(&self.progress).callback = None;
+        self.sync_progress_callback();
+    }
+
+    /// Cancels `compute_charts`, `pack_charts` and `generate` once the token is cancelled.
+    /// They return `Cancelled::Token` at the next progress report.
+    pub fn set_cancellation_token(&mut self, token: Option<CancellationToken>) {
+        progress::lock(&self.progress).token = token;
+        self.sync_progress_callback();
+    }
+
+    /// Cancels `compute_charts`, `pack_charts` and `generate` once `deadline` has passed.
+    /// They return `Cancelled::Deadline` at the next progress report.
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        progress::lock(&self.progress).deadline = deadline;
+        self.sync_progress_callback();
     }
-}
 
-/// Callback type that fits inside of a *void. Note that a single Box would not fit
-/// because it is 128 bits instead of 64
-type ProgressCallbackPointer = Pin<Box<Box<dyn Fn(ProgressCategory, i32) -> bool>>>;
+    /// Only installs the trampoline while there is something for it to do.
+    fn sync_progress_callback(&mut self) {
+        if progress::lock(&self.progress).is_active() {
+            let user_data = &*self.progress as *const Mutex<Progress> as *mut _;
+            unsafe {
+                xatlas::SetProgressCallback(
+                    self.handle,
+                    Some(progress::progress_callback),
+                    user_data,
+                )
+            }
+        } else {
+            unsafe { xatlas::SetProgressCallback(self.handle, None, std::ptr::null_mut()) }
+        }
+    }
 
-unsafe extern "C" fn progress_callback(
-    category: xatlas::ProgressCategory,
-    progress: std::os::raw::c_int,
-    user_data: *mut std::os::raw::c_void,
-) -> bool {
-    let progress: i32 = progress;
-    let category = match category {
-        xatlas::ProgressCategory_AddMesh => ProgressCategory::AddMesh,
-        xatlas::ProgressCategory_ComputeCharts => ProgressCategory::ComputeCharts,
-        xatlas::ProgressCategory_PackCharts => ProgressCategory::PackCharts,
-        xatlas::ProgressCategory_BuildOutputMeshes => ProgressCategory::BuildOutputMeshes,
-        _ => unreachable!(),
-    };
+    /// Limits the memory this atlas's xatlas data may use to `limit` bytes. The allocator checks
+    /// the limit on every allocation, and once it is exceeded `compute_charts`, `pack_charts` and
+    /// `generate` stop at the next progress report with `Cancelled::MemoryLimit`. The allocation
+    /// that exceeds the limit still succeeds, since xatlas can't handle failed allocations.
+    ///
+    /// Only memory allocated while the limit is set is counted, so set it before adding meshes.
+    /// To attribute allocations to this atlas, its operations don't run concurrently with those
+    /// of other atlases while it has a limit, so its callbacks must not call into other atlases.
+    ///
+    /// Returns `AllocatorInUse` if xatlas doesn't use the Rust allocator, see
+    /// `use_rust_allocator`, since the allocations can't be counted then.
+    pub fn set_memory_limit(&mut self, limit: Option<usize>) -> Result<(), AllocatorInUse> {
+        if limit.is_some() && !memory::is_rust_allocator() {
+            return Err(AllocatorInUse);
+        }
+        self.memory.set_limit(limit);
+        progress::lock(&self.progress).memory = limit.map(|_| self.memory.clone());
+        self.sync_progress_callback();
+        Ok(())
+    }
 
-    let callback: ProgressCallbackPointer = unsafe { mem::transmute(user_data) };
-    let result = callback(category, progress);
-    mem::forget(callback);
+    /// Runs an operation that can be cancelled through the progress callback.
+    /// A parameterizer panic returned by `operation` takes precedence over other cancellations.
+    fn cancellable(
+        &mut self,
+        operation: impl FnOnce(*mut xatlas::Atlas) -> Result<(), Cancelled>,
+    ) -> Result<(), Cancelled> {
+        let _operation = self.memory.begin();
+        {
+            let mut progress = progress::lock(&self.progress);
+            progress.cancelled = None;
+            progress.check()?;
+        }
+        let result = operation(self.handle);
+        let cancelled = progress::lock(&self.progress).cancelled.take();
+        result?;
+        match cancelled {
+            None => Ok(()),
+            Some(cancelled) => Err(cancelled),
+        }
+    }
+}
 
-    result
+/// `slice::from_raw_parts` doesn't accept null pointers, which xatlas uses for empty arrays.
+unsafe fn raw_slice<'a, T>(data: *const T, count: u32) -> &'a [T] {
+    if data.is_null() || count == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(data, count as usize)
+    }
 }
 
 fn add_mesh_error_result(add_mesh_error: xatlas::AddMeshError) -> Result<(), AddMeshError> {
     match add_mesh_error {
         xatlas::AddMeshError_Success => Ok(()),
-        xatlas::AddMeshError_Error => Err(AddMeshError::Error),
-        xatlas::AddMeshError_IndexOutOfRange => Err(AddMeshError::IndexOutOfRange),
-        xatlas::AddMeshError_InvalidFaceVertexCount => Err(AddMeshError::InvalidFaceVertexCount),
-        xatlas::AddMeshError_InvalidIndexCount => Err(AddMeshError::InvalidIndexCount),
-        _ => unreachable!(),
+        // The remaining errors are caught with more detail by the validate methods,
+        // so xatlas only gets to report them if the two disagree.
+        code => Err(AddMeshError::Error { code }),
+    }
+}
+
+fn add_mesh_error_name(code: i32) -> &'static str {
+    match code {
+        xatlas::AddMeshError_Error => "error",
+        xatlas::AddMeshError_IndexOutOfRange => "index out of range",
+        xatlas::AddMeshError_InvalidFaceVertexCount => "invalid face vertex count",
+        xatlas::AddMeshError_InvalidIndexCount => "invalid index count",
+        _ => "unknown error",
+    }
+}
+
+impl fmt::Display for AddMeshError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AddMeshError::Error { code } => write!(
+                f,
+                "xatlas failed to add the mesh: {} ({code})",
+                add_mesh_error_name(*code)
+            ),
+            AddMeshError::IndexOutOfRange {
+                face,
+                corner,
+                index,
+                vertex_count,
+            } => write!(
+                f,
+                "index {index} of face {face} corner {corner} is out of range for {vertex_count} vertices"
+            ),
+            AddMeshError::InvalidFaceVertexCount { face, count } => {
+                write!(f, "face {face} has {count} vertices, expected at least 3")
+            }
+            AddMeshError::InvalidIndexCount { index_count } => {
+                write!(f, "index count {index_count} is not divisible by 3")
+            }
+            AddMeshError::CornerCountMismatch { expected, actual } => write!(
+                f,
+                "face vertex counts add up to {actual}, but there are {expected} indices"
+            ),
+            AddMeshError::FaceVertexCountLengthMismatch { expected, actual } => write!(
+                f,
+                "face vertex count data has {actual} entries, expected {expected}"
+            ),
+            AddMeshError::FaceIgnoreLengthMismatch { expected, actual } => write!(
+                f,
+                "face ignore data has {actual} entries, expected {expected}"
+            ),
+            AddMeshError::MaterialLengthMismatch { expected, actual } => write!(
+                f,
+                "face material data has {actual} entries, expected {expected}"
+            ),
+            AddMeshError::ComponentMismatch {
+                stream,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{stream} data has {actual} components, expected {expected}"
+            ),
+            AddMeshError::InvalidDataLength {
+                stream,
+                len,
+                components,
+            } => write!(
+                f,
+                "{stream} data has {len} floats, which is not a multiple of {components}"
+            ),
+            AddMeshError::StrideTooSmall {
+                stream,
+                stride,
+                min,
+            } => write!(
+                f,
+                "{stream} stride of {stride} bytes is smaller than the element size of {min} bytes"
+            ),
+            AddMeshError::VertexCountMismatch {
+                stream,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{stream} data has {actual} vertices, expected {expected}"
+            ),
+            AddMeshError::NonFinitePosition { vertex } => {
+                write!(f, "position of vertex {vertex} is not finite")
+            }
+            AddMeshError::AttributeOutsideStride {
+                stream,
+                end,
+                stride,
+            } => write!(
+                f,
+                "{stream} attribute ends at byte {end}, past the vertex stride of {stride} bytes"
+            ),
+            AddMeshError::TooManyFaceVertices { face, count } => {
+                write!(f, "face {face} has {count} vertices, at most 255 are supported")
+            }
+            AddMeshError::BufferLengthMismatch { len, stride } => write!(
+                f,
+                "vertex buffer of {len} bytes is not a multiple of the vertex stride of {stride} bytes"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AddMeshError {}
+
+impl fmt::Display for VertexStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            VertexStream::Position => "position",
+            VertexStream::Normal => "normal",
+            VertexStream::Uv => "uv",
+        })
     }
 }
 
 impl ChartOptions {
+    /// Parameterizes charts with a Rust closure instead of xatlas's own LSCM solver, while still
+    /// using xatlas's segmentation and packing. The closure receives a chart's vertex positions,
+    /// its texture coordinates (initially an orthogonal projection) to overwrite, and its triangle
+    /// indices. It may be called from several threads at once.
+    ///
+    /// xatlas gives no way to tell atlases apart inside the callback, so atlases using a
+    /// parameterizer compute their charts one at a time, and the closure must not compute the
+    /// charts of another atlas with a parameterizer. If the closure panics, the remaining charts
+    /// keep their projection and the operation returns `Cancelled::Panicked`.
+    pub fn with_parameterizer(
+        mut self,
+        parameterizer: impl Fn(&[[f32; 3]], &mut [[f32; 2]], &[u32]) + Send + Sync + 'static,
+    ) -> Self {
+        self.parameterizer = Some(Arc::new(parameterizer));
+        self
+    }
+
     fn convert(&self) -> xatlas::ChartOptions {
         xatlas::ChartOptions {
-            paramFunc: self.param_func,
+            paramFunc: match self.parameterizer {
+                None => self.param_func,
+                Some(_) => Some(parameterize::parameterize),
+            },
             maxChartArea: self.max_chart_area,
             maxBoundaryLength: self.max_boundary_length,
             normalDeviationWeight: self.normal_deviation_weight,
@@ -472,7 +954,7 @@ impl ChartOptions {
     }
 }
 
-impl Default for Xatlas<'_> {
+impl Default for Xatlas {
     fn default() -> Self {
         Self::new()
     }
@@ -482,6 +964,7 @@ impl Default for ChartOptions {
     fn default() -> Self {
         ChartOptions {
             param_func: None,
+            parameterizer: None,
             max_chart_area: 0.0,
             max_boundary_length: 0.0,
             normal_deviation_weight: 2.0,
@@ -547,25 +1030,386 @@ impl Default for MeshDecl<'_> {
     }
 }
 
+impl<'a> MeshDecl<'a> {
+    /// A declaration of the given positions, e.g. a `&[[f32; 3]]`, or with the `glam`, `mint`
+    /// or `nalgebra` features a slice of their vector types. Without index data every 3
+    /// vertices form a triangle.
+    pub fn new(positions: impl Into<MeshData<'a>>) -> Self {
+        MeshDecl {
+            vertex_position_data: positions.into(),
+            ..MeshDecl::default()
+        }
+    }
+
+    pub fn with_normals(mut self, normals: impl Into<MeshData<'a>>) -> Self {
+        self.vertex_normal_data = Some(normals.into());
+        self
+    }
+
+    pub fn with_uvs(mut self, uvs: impl Into<MeshData<'a>>) -> Self {
+        self.vertex_uv_data = Some(uvs.into());
+        self
+    }
+
+    pub fn with_indices(mut self, indices: impl Into<IndexData<'a>>) -> Self {
+        self.index_data = Some(indices.into());
+        self
+    }
+
+    /// Declaration of an indexed polygon mesh as the file loaders store it. Empty normals and
+    /// UVs are left out. Face vertex counts are only passed when some face isn't a triangle;
+    /// no counts at all means every face is a triangle.
+    #[cfg(any(feature = "gltf", feature = "obj", feature = "ply"))]
+    pub(crate) fn from_polygons(
+        positions: &'a [[f32; 3]],
+        normals: &'a [[f32; 3]],
+        uvs: &'a [[f32; 2]],
+        indices: &'a [u32],
+        face_vertex_counts: &'a [u8],
+    ) -> Self {
+        let has_polygons = face_vertex_counts.iter().any(|&count| count != 3);
+        let face_count = if face_vertex_counts.is_empty() {
+            indices.len() / 3
+        } else {
+            face_vertex_counts.len()
+        };
+
+        MeshDecl {
+            vertex_position_data: MeshData::Vec3(positions),
+            vertex_normal_data: (!normals.is_empty()).then_some(MeshData::Vec3(normals)),
+            vertex_uv_data: (!uvs.is_empty()).then_some(MeshData::Vec2(uvs)),
+            index_data: Some(IndexData::U32(indices)),
+            face_vertex_count: has_polygons.then_some(face_vertex_counts),
+            face_count: face_count as u32,
+            ..MeshDecl::default()
+        }
+    }
+}
+
+impl MeshDecl<'_> {
+    /// Checks the declaration without passing it to xatlas, reporting exactly which stream,
+    /// face or index is wrong. `Xatlas::add_mesh` calls this before adding the mesh.
+    pub fn validate(&self) -> Result<(), AddMeshError> {
+        let vertex_count = self
+            .vertex_position_data
+            .checked_count(VertexStream::Position)?;
+        for (stream, data) in [
+            (VertexStream::Normal, &self.vertex_normal_data),
+            (VertexStream::Uv, &self.vertex_uv_data),
+        ] {
+            if let Some(data) = data {
+                let count = data.checked_count(stream)?;
+                if count != vertex_count {
+                    return Err(AddMeshError::VertexCountMismatch {
+                        stream,
+                        expected: vertex_count,
+                        actual: count,
+                    });
+                }
+            }
+        }
+
+        for vertex in 0..vertex_count {
+            let position = self.vertex_position_data.read(vertex, 3);
+            if !position.iter().all(|c| c.is_finite()) {
+                return Err(AddMeshError::NonFinitePosition { vertex });
+            }
+        }
+
+        let index_count = self.index_data.as_ref().map_or(vertex_count, |d| d.count());
+        let face_count = self.resolved_face_count();
+        match self.face_vertex_count {
+            Some(counts) => {
+                if counts.len() != face_count as usize {
+                    return Err(AddMeshError::FaceVertexCountLengthMismatch {
+                        expected: face_count,
+                        actual: counts.len(),
+                    });
+                }
+                if let Some(face) = counts.iter().position(|&count| count < 3) {
+                    return Err(AddMeshError::InvalidFaceVertexCount {
+                        face: face as u32,
+                        count: counts[face],
+                    });
+                }
+                let corners = counts.iter().map(|&count| count as u64).sum();
+                if corners != index_count as u64 {
+                    return Err(AddMeshError::CornerCountMismatch {
+                        expected: index_count,
+                        actual: corners,
+                    });
+                }
+            }
+            None if !index_count.is_multiple_of(3) => {
+                return Err(AddMeshError::InvalidIndexCount { index_count });
+            }
+            None => {}
+        }
+
+        if let Some(index_data) = &self.index_data {
+            let mut position = 0;
+            for face in 0..face_count {
+                let corners = self
+                    .face_vertex_count
+                    .map_or(3, |d| d[face as usize] as u32);
+                for corner in 0..corners {
+                    let index = index_data.get(position);
+                    if index >= vertex_count {
+                        return Err(AddMeshError::IndexOutOfRange {
+                            face,
+                            corner,
+                            index,
+                            vertex_count,
+                        });
+                    }
+                    position += 1;
+                }
+            }
+        }
+
+        if let Some(d) = self.face_ignore_data {
+            if d.len() != face_count as usize {
+                return Err(AddMeshError::FaceIgnoreLengthMismatch {
+                    expected: face_count,
+                    actual: d.len(),
+                });
+            }
+        }
+        if let Some(d) = self.face_material_data {
+            if d.len() != face_count as usize {
+                return Err(AddMeshError::MaterialLengthMismatch {
+                    expected: face_count,
+                    actual: d.len(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Number of faces xatlas will see for this declaration.
+    fn resolved_face_count(&self) -> u32 {
+        match self.face_vertex_count {
+            Some(d) if self.face_count == 0 => d.len() as u32,
+            Some(_) => self.face_count,
+            None => match &self.index_data {
+                Some(d) => d.count() / 3,
+                None => self.vertex_position_data.count(3) / 3,
+            },
+        }
+    }
+}
+
+impl UvMeshDecl<'_> {
+    /// Checks the declaration without passing it to xatlas. `Xatlas::add_uv_mesh` calls this
+    /// before adding the mesh.
+    pub fn validate(&self) -> Result<(), AddMeshError> {
+        let vertex_count = match &self.vertex_uv_data {
+            None => 0,
+            Some(d) => d.checked_count(VertexStream::Uv)?,
+        };
+
+        let index_count = self.index_data.as_ref().map_or(vertex_count, |d| d.count());
+        if !index_count.is_multiple_of(3) {
+            return Err(AddMeshError::InvalidIndexCount { index_count });
+        }
+
+        if let Some(index_data) = &self.index_data {
+            for position in 0..index_count {
+                let index = index_data.get(position);
+                if index >= vertex_count {
+                    return Err(AddMeshError::IndexOutOfRange {
+                        face: position / 3,
+                        corner: position % 3,
+                        index,
+                        vertex_count,
+                    });
+                }
+            }
+        }
+
+        if let Some(d) = self.face_material_data {
+            if d.len() != (index_count / 3) as usize {
+                return Err(AddMeshError::MaterialLengthMismatch {
+                    expected: index_count / 3,
+                    actual: d.len(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
 impl MeshData<'_> {
     fn as_ptr(&self) -> *const u8 {
         match self {
             MeshData::Contiguous(d) => d.as_ptr() as _,
             MeshData::WithStride { data, .. } => data.as_ptr(),
+            MeshData::Vec2(d) => d.as_ptr() as _,
+            MeshData::Vec3(d) => d.as_ptr() as _,
+            MeshData::Encoded { data, .. } => data.as_ptr(),
+        }
+    }
+
+    /// Number of components per vertex, if the variant carries it.
+    fn components(&self) -> Option<u32> {
+        match self {
+            MeshData::Contiguous(_) | MeshData::WithStride { .. } | MeshData::Encoded { .. } => {
+                None
+            }
+            MeshData::Vec2(_) => Some(2),
+            MeshData::Vec3(_) => Some(3),
         }
     }
 
     fn stride(&self, num: u32) -> u32 {
         match self {
-            MeshData::Contiguous(_) => num * std::mem::size_of::<f32>() as u32,
-            MeshData::WithStride { stride, .. } => *stride,
+            MeshData::WithStride { stride, .. } | MeshData::Encoded { stride, .. } => *stride,
+            _ => num * std::mem::size_of::<f32>() as u32,
         }
     }
 
-    fn count(&self) -> u32 {
+    /// Number of vertices, given the number of components the stream is read with.
+    fn count(&self, num: u32) -> u32 {
         match self {
-            MeshData::Contiguous(d) => (d.len() / 3) as u32,
-            MeshData::WithStride { data, stride, .. } => data.len() as u32 / stride,
+            MeshData::Contiguous(d) => (d.len() / num as usize) as u32,
+            MeshData::WithStride { data, stride } => {
+                let element_size = num as usize * std::mem::size_of::<f32>();
+                if *stride == 0 || data.len() < element_size {
+                    0
+                } else {
+                    ((data.len() - element_size) / *stride as usize + 1) as u32
+                }
+            }
+            MeshData::Vec2(d) => d.len() as u32,
+            MeshData::Vec3(d) => d.len() as u32,
+            MeshData::Encoded {
+                data,
+                stride,
+                format,
+                ..
+            } => {
+                let element_size = num as usize * format.size();
+                if *stride == 0 || data.len() < element_size {
+                    0
+                } else {
+                    ((data.len() - element_size) / *stride as usize + 1) as u32
+                }
+            }
+        }
+    }
+
+    /// Reads the first `num` components of a vertex, the rest are left at zero.
+    /// `index` must be less than `count(num)`.
+    pub(crate) fn read(&self, index: u32, num: u32) -> [f32; 3] {
+        let (index, num) = (index as usize, num as usize);
+        let mut out = [0.0; 3];
+        match self {
+            MeshData::Contiguous(d) => {
+                out[..num].copy_from_slice(&d[index * num..(index + 1) * num]);
+            }
+            MeshData::WithStride { data, stride } => {
+                let start = index * *stride as usize;
+                for (c, out) in out.iter_mut().enumerate().take(num) {
+                    let offset = start + c * std::mem::size_of::<f32>();
+                    let mut bytes = [0; 4];
+                    bytes.copy_from_slice(&data[offset..offset + 4]);
+                    *out = f32::from_ne_bytes(bytes);
+                }
+            }
+            MeshData::Vec2(d) => out[..2].copy_from_slice(&d[index]),
+            MeshData::Vec3(d) => out = d[index],
+            MeshData::Encoded {
+                data,
+                stride,
+                format,
+                dequantize,
+            } => {
+                let start = index * *stride as usize;
+                for (c, out) in out.iter_mut().enumerate().take(num) {
+                    let offset = start + c * format.size();
+                    *out = dequantize.apply(c, format.read(&data[offset..]));
+                }
+            }
+        }
+        out
+    }
+
+    /// Pointer and stride of the data as xatlas reads it. xatlas only reads aligned f32, so
+    /// encoded data, and strided data that isn't 4 byte aligned, is decoded into `buffer`,
+    /// which has to outlive the pointer.
+    fn float_data(&self, num: u32, buffer: &mut Vec<f32>) -> (*const u8, u32) {
+        let align = std::mem::align_of::<f32>();
+        let decode = match self {
+            MeshData::Encoded { .. } => true,
+            MeshData::WithStride { data, stride } => {
+                !(data.as_ptr() as usize).is_multiple_of(align)
+                    || !(*stride as usize).is_multiple_of(align)
+            }
+            _ => false,
+        };
+        if !decode {
+            return (self.as_ptr(), self.stride(num));
+        }
+        buffer.clear();
+        for index in 0..self.count(num) {
+            buffer.extend_from_slice(&self.read(index, num)[..num as usize]);
+        }
+        (
+            buffer.as_ptr() as _,
+            num * std::mem::size_of::<f32>() as u32,
+        )
+    }
+
+    /// Like `count`, but checks that the data matches the layout of `stream`.
+    fn checked_count(&self, stream: VertexStream) -> Result<u32, AddMeshError> {
+        let components = stream.components();
+        if let Some(actual) = self.components() {
+            if actual != components {
+                return Err(AddMeshError::ComponentMismatch {
+                    stream,
+                    expected: components,
+                    actual,
+                });
+            }
+        }
+        match self {
+            MeshData::Contiguous(d) if d.len() % components as usize != 0 => {
+                return Err(AddMeshError::InvalidDataLength {
+                    stream,
+                    len: d.len(),
+                    components,
+                });
+            }
+            MeshData::WithStride { stride, .. } if *stride < components * 4 => {
+                return Err(AddMeshError::StrideTooSmall {
+                    stream,
+                    stride: *stride,
+                    min: components * 4,
+                });
+            }
+            MeshData::Encoded { stride, format, .. }
+                if *stride < components * format.size() as u32 =>
+            {
+                return Err(AddMeshError::StrideTooSmall {
+                    stream,
+                    stride: *stride,
+                    min: components * format.size() as u32,
+                });
+            }
+            _ => {}
+        }
+        Ok(self.count(components))
+    }
+}
+
+impl VertexStream {
+    /// Number of f32 components xatlas reads for this stream.
+    pub fn components(self) -> u32 {
+        match self {
+            VertexStream::Position | VertexStream::Normal => 3,
+            VertexStream::Uv => 2,
         }
     }
 }
@@ -584,9 +1428,120 @@ impl IndexData<'_> {
             IndexData::U32(d) => d.len(),
         }) as _
     }
+
+    pub(crate) fn get(&self, position: u32) -> u32 {
+        match self {
+            IndexData::U16(d) => d[position as usize] as u32,
+            IndexData::U32(d) => d[position as usize],
+        }
+    }
 }
 
-impl Drop for Xatlas<'_> {
+impl AtlasOutput {
+    /// The texels of atlas `atlas_index`, or `None` if there is no image or no such atlas.
+    pub fn image_page(&self, atlas_index: u32) -> Option<ImagePage<'_>> {
+        ImagePage::new(self.image.as_deref()?, self.width, self.height, atlas_index)
+    }
+
+    /// Texel density of every chart and mesh, see `DensityReport::compute`.
+    pub fn density(&self, inputs: &[MeshDecl<'_>]) -> DensityReport {
+        DensityReport::compute(inputs, self)
+    }
+
+    /// UV distortion of every chart and mesh, see `AtlasMetrics::compute`.
+    pub fn metrics(&self, inputs: &[MeshDecl<'_>]) -> AtlasMetrics {
+        AtlasMetrics::compute(inputs, self)
+    }
+
+    /// Draws the UV layout of atlas `atlas_index` as SVG, see `SvgOptions`.
+    pub fn to_svg(&self, atlas_index: u32) -> String {
+        self.to_svg_with_options(atlas_index, &SvgOptions::default())
+    }
+
+    pub fn to_svg_with_options(&self, atlas_index: u32, options: &SvgOptions) -> String {
+        svg::render(&self.meshes, self.width, self.height, atlas_index, options)
+    }
+
+    /// Checks the layout for overlapping charts, flipped triangles and charts outside the atlas.
+    /// `resolution` is the size of an atlas, 0 uses `width` and `height`. `padding` is the
+    /// minimum number of texels between charts, usually `PackOptions::padding`.
+    pub fn validate(&self, resolution: u32, padding: u32) -> LayoutReport {
+        validate::validate(self, resolution, padding)
+    }
+}
+
+impl Mesh<'_> {
+    /// Rebuilds a per-vertex attribute of the input mesh, such as tangents or colors, for the
+    /// output vertices. `input` has one element per input vertex.
+    ///
+    /// # Panics
+    ///
+    /// If `input` has fewer elements than the input mesh has vertices.
+    pub fn remap_attribute<T: Copy>(&self, input: &[T]) -> Vec<T> {
+        attribute::remap(self, input)
+    }
+
+    /// Like `remap_attribute`, for an attribute of `size` bytes that repeats every `stride`
+    /// bytes of `input`. To read an attribute of an interleaved buffer, start `input` at the
+    /// attribute's offset. The result is tightly packed.
+    ///
+    /// # Panics
+    ///
+    /// If `input` is too short for the input mesh's vertices.
+    pub fn remap_attribute_strided(&self, input: &[u8], stride: usize, size: usize) -> Vec<u8> {
+        attribute::remap_strided(self, input, stride, size)
+    }
+
+    /// Rebuilds an interleaved vertex buffer laid out as `layout` for the output vertices, and
+    /// writes the atlas UV into the layout's `uv` attribute. UVs are in texels multiplied by
+    /// `uv_scale`, use `[1.0 / width, 1.0 / height]` to get UVs in the 0 to 1 range.
+    ///
+    /// # Panics
+    ///
+    /// If `input` is too short for the input mesh's vertices, or the layout has no `uv`
+    /// attribute or it doesn't fit in the stride.
+    pub fn remap_interleaved(
+        &self,
+        input: &[u8],
+        layout: &VertexLayout,
+        uv_scale: [f32; 2],
+    ) -> Vec<u8> {
+        attribute::remap_interleaved(self, input, layout, uv_scale)
+    }
+
+    /// The atlas UVs of the output vertices as two tightly packed components in `format`,
+    /// ready to upload. UVs are in texels multiplied by `uv_scale`, normalized formats need
+    /// `[1.0 / width, 1.0 / height]` and clamp anything outside their range.
+    pub fn encode_uvs(&self, format: AttrFormat, uv_scale: [f32; 2]) -> Vec<u8> {
+        attribute::encode_uvs(self, format, uv_scale)
+    }
+
+    /// Copies any borrowed data so the mesh no longer depends on the `Xatlas` it came from.
+    pub fn into_owned(self) -> Mesh<'static> {
+        Mesh {
+            index_array: Cow::Owned(self.index_array.into_owned()),
+            chart_array: self
+                .chart_array
+                .into_iter()
+                .map(Chart::into_owned)
+                .collect(),
+            vertex_array: self.vertex_array,
+        }
+    }
+}
+
+impl Chart<'_> {
+    pub fn into_owned(self) -> Chart<'static> {
+        Chart {
+            face_array: Cow::Owned(self.face_array.into_owned()),
+            atlas_index: self.atlas_index,
+            type_: self.type_,
+            material: self.material,
+        }
+    }
+}
+
+impl Drop for Xatlas {
     fn drop(&mut self) {
         unsafe {
             xatlas::Destroy(self.handle);
@@ -594,4 +1549,224 @@ impl Drop for Xatlas<'_> {
     }
 }
 
-unsafe impl Send for Xatlas<'_> {}
+unsafe impl Send for Xatlas {}
+// Shared references only read the results.
+unsafe impl Sync for Xatlas {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUAD: [[f32; 3]; 4] = [
+        [0.0, 0.0, 0.0],
+        [1.0, 0.0, 0.0],
+        [1.0, 1.0, 0.0],
+        [0.0, 1.0, 0.0],
+    ];
+
+    #[test]
+    fn validate_accepts_triangles_and_polygons() {
+        let indices = [0u32, 1, 2, 0, 2, 3];
+        assert_eq!(
+            MeshDecl::new(&QUAD[..])
+                .with_indices(&indices[..])
+                .validate(),
+            Ok(())
+        );
+
+        let quad = [0u32, 1, 2, 3];
+        let decl = MeshDecl {
+            face_vertex_count: Some(&[4]),
+            ..MeshDecl::new(&QUAD[..]).with_indices(&quad[..])
+        };
+        assert_eq!(decl.validate(), Ok(()));
+    }
+
+    #[test]
+    fn validate_reports_the_face_and_corner_of_an_out_of_range_index() {
+        let indices = [0u32, 1, 2, 0, 4, 3];
+        let decl = MeshDecl::new(&QUAD[..]).with_indices(&indices[..]);
+        assert_eq!(
+            decl.validate(),
+            Err(AddMeshError::IndexOutOfRange {
+                face: 1,
+                corner: 1,
+                index: 4,
+                vertex_count: 4,
+            })
+        );
+
+        let indices = [0u32, 1, 2, 0, 2, 3, 7];
+        let decl = MeshDecl {
+            face_vertex_count: Some(&[3, 4]),
+            ..MeshDecl::new(&QUAD[..]).with_indices(&indices[..])
+        };
+        assert_eq!(
+            decl.validate(),
+            Err(AddMeshError::IndexOutOfRange {
+                face: 1,
+                corner: 3,
+                index: 7,
+                vertex_count: 4,
+            })
+        );
+    }
+
+    #[test]
+    fn misaligned_strided_data_is_copied_into_an_aligned_buffer() {
+        // Every vertex is a flag byte followed by a position, so the positions are misaligned.
+        let mut data = Vec::new();
+        for position in &QUAD {
+            data.push(1);
+            for value in position {
+                data.extend_from_slice(&value.to_ne_bytes());
+            }
+        }
+        let layout = VertexLayout {
+            stride: 13,
+            position: Attr::new(1, AttrFormat::F32),
+            normal: None,
+            uv: None,
+        };
+        let decl = layout.mesh_decl(&data).unwrap();
+        let mut buffer = Vec::new();
+        let (pointer, stride) = decl.vertex_position_data.float_data(3, &mut buffer);
+        assert_eq!(pointer, buffer.as_ptr() as *const u8);
+        assert_eq!(stride, 12);
+        assert_eq!(buffer, QUAD.concat());
+    }
+
+    #[test]
+    fn validate_rejects_a_stride_smaller_than_a_vertex() {
+        let data = [0u8; 32];
+        let decl = MeshDecl::new(MeshData::WithStride {
+            data: &data,
+            stride: 8,
+        });
+        assert_eq!(
+            decl.validate(),
+            Err(AddMeshError::StrideTooSmall {
+                stream: VertexStream::Position,
+                stride: 8,
+                min: 12,
+            })
+        );
+    }
+
+    #[test]
+    fn validate_rejects_non_finite_positions() {
+        let mut positions = QUAD;
+        positions[2][1] = f32::NAN;
+        let indices = [0u32, 1, 2, 0, 2, 3];
+        let decl = MeshDecl::new(&positions[..]).with_indices(&indices[..]);
+        assert_eq!(
+            decl.validate(),
+            Err(AddMeshError::NonFinitePosition { vertex: 2 })
+        );
+
+        positions[2][1] = f32::INFINITY;
+        let decl = MeshDecl::new(&positions[..]).with_indices(&indices[..]);
+        assert_eq!(
+            decl.validate(),
+            Err(AddMeshError::NonFinitePosition { vertex: 2 })
+        );
+    }
+
+    #[test]
+    fn validate_checks_per_face_data_lengths() {
+        let indices = [0u32, 1, 2, 0, 2, 3];
+        let decl = MeshDecl {
+            face_material_data: Some(&[0, 1, 2]),
+            ..MeshDecl::new(&QUAD[..]).with_indices(&indices[..])
+        };
+        assert_eq!(
+            decl.validate(),
+            Err(AddMeshError::MaterialLengthMismatch {
+                expected: 2,
+                actual: 3,
+            })
+        );
+
+        let decl = MeshDecl {
+            face_vertex_count: Some(&[3, 3, 3]),
+            face_count: 2,
+            ..MeshDecl::new(&QUAD[..]).with_indices(&indices[..])
+        };
+        assert_eq!(
+            decl.validate(),
+            Err(AddMeshError::FaceVertexCountLengthMismatch {
+                expected: 2,
+                actual: 3,
+            })
+        );
+    }
+
+    #[test]
+    fn validate_rejects_face_vertex_counts_that_dont_match_the_indices() {
+        let indices = [0u32, 1, 2, 0, 2, 3];
+        let decl = MeshDecl {
+            face_vertex_count: Some(&[3, 4]),
+            ..MeshDecl::new(&QUAD[..]).with_indices(&indices[..])
+        };
+        assert_eq!(
+            decl.validate(),
+            Err(AddMeshError::CornerCountMismatch {
+                expected: 6,
+                actual: 7,
+            })
+        );
+    }
+
+    #[test]
+    fn add_mesh_source_validates_a_decl_before_reading_it() {
+        let mut atlas = Xatlas::new();
+        let indices = [0u32, 1, 2, 0, 2, 3];
+        let short_normals = [[0.0, 0.0, 1.0]; 3];
+
+        let short_counts = MeshDecl {
+            face_vertex_count: Some(&[3]),
+            face_count: 2,
+            ..MeshDecl::new(&QUAD[..]).with_indices(&indices[..])
+        };
+        let short_indices = MeshDecl {
+            face_vertex_count: Some(&[3, 4]),
+            ..MeshDecl::new(&QUAD[..]).with_indices(&indices[..])
+        };
+        let short_normals = MeshDecl {
+            vertex_normal_data: Some(MeshData::Vec3(&short_normals)),
+            ..MeshDecl::new(&QUAD[..]).with_indices(&indices[..])
+        };
+        let short_materials = MeshDecl {
+            face_material_data: Some(&[0]),
+            ..MeshDecl::new(&QUAD[..]).with_indices(&indices[..])
+        };
+        for decl in [short_counts, short_indices, short_normals, short_materials] {
+            let expected = decl.validate();
+            assert!(expected.is_err());
+            assert_eq!(atlas.add_mesh_source(&decl), expected);
+        }
+    }
+
+    #[test]
+    fn xatlas_error_codes_are_kept() {
+        assert_eq!(add_mesh_error_result(xatlas::AddMeshError_Success), Ok(()));
+        let error = add_mesh_error_result(xatlas::AddMeshError_IndexOutOfRange).unwrap_err();
+        assert_eq!(
+            error,
+            AddMeshError::Error {
+                code: xatlas::AddMeshError_IndexOutOfRange
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "xatlas failed to add the mesh: index out of range ({})",
+                xatlas::AddMeshError_IndexOutOfRange
+            )
+        );
+        assert_eq!(
+            add_mesh_error_result(100),
+            Err(AddMeshError::Error { code: 100 })
+        );
+    }
+}
@@ -0,0 +1,37 @@
+//! Error shared by the file loaders whose parse errors are plain messages.
+
+use std::fmt;
+use std::io;
+
+/// Error returned by `ObjMesh`, `PlyMesh` and `StlMesh` loading, also available as
+/// `obj::ObjError`, `ply::PlyError` and `stl::StlError`.
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    /// The file is malformed. The message says where, when the format has lines.
+    Parse(String),
+}
+
+impl From<io::Error> for LoadError {
+    fn from(e: io::Error) -> Self {
+        LoadError::Io(e)
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io(e) => e.fmt(f),
+            LoadError::Parse(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::Io(e) => Some(e),
+            LoadError::Parse(_) => None,
+        }
+    }
+}
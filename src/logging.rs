@@ -0,0 +1,113 @@
+//! Optional integration with the `log` and `tracing` crates.
+//!
+//! With either feature enabled, xatlas's own messages are forwarded under the `xatlas` target.
+//! With `tracing`, the atlas operations are additionally wrapped in spans that record the number
+//! of meshes and the time the operation took.
+
+/// Enters an `info` span for an atlas operation, which records `elapsed_ms` when it ends.
+/// Does nothing without the `tracing` feature.
+macro_rules! operation_span {
+    ($name:literal, $mesh_count:expr) => {{
+        #[cfg(feature = "tracing")]
+        let span = crate::logging::OperationSpan::enter(tracing::info_span!(
+            $name,
+            mesh_count = $mesh_count,
+            elapsed_ms = tracing::field::Empty
+        ));
+        #[cfg(not(feature = "tracing"))]
+        let span = crate::logging::OperationSpan;
+        span
+    }};
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct OperationSpan;
+
+#[cfg(feature = "tracing")]
+pub(crate) struct OperationSpan {
+    span: tracing::span::EnteredSpan,
+    start: std::time::Instant,
+}
+
+#[cfg(feature = "tracing")]
+impl OperationSpan {
+    pub(crate) fn enter(span: tracing::Span) -> Self {
+        Self {
+            span: span.entered(),
+            start: std::time::Instant::now(),
+        }
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl Drop for OperationSpan {
+    fn drop(&mut self) {
+        let elapsed = self.start.elapsed().as_secs_f64() * 1000.0;
+        self.span.record("elapsed_ms", elapsed);
+    }
+}
+
+#[cfg(any(feature = "log", feature = "tracing"))]
+mod print {
+    use crate::root::xatlas;
+    use std::ffi::CStr;
+    use std::os::raw::{c_char, c_int};
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::Mutex;
+
+    extern "C" {
+        /// Defined in print.cpp, formats the message and passes it to `xatlas_rs_log_message`.
+        fn xatlas_rs_print(format: *const c_char, ...) -> c_int;
+    }
+
+    #[no_mangle]
+    extern "C" fn xatlas_rs_log_message(message: *const c_char) {
+        let message = unsafe { CStr::from_ptr(message) }.to_string_lossy();
+        let message = message.trim();
+        if message.is_empty() {
+            return;
+        }
+
+        // A panicking logger must not unwind into xatlas.
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| forward(message)));
+    }
+
+    /// xatlas doesn't pass a severity, so every message is logged at debug level.
+    #[cfg(feature = "tracing")]
+    fn forward(message: &str) {
+        tracing::debug!(target: "xatlas", "{}", message);
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    fn forward(message: &str) {
+        log::debug!(target: "xatlas", "{}", message);
+    }
+
+    fn verbose() -> bool {
+        #[cfg(feature = "tracing")]
+        let verbose = tracing::enabled!(target: "xatlas", tracing::Level::DEBUG);
+        #[cfg(not(feature = "tracing"))]
+        let verbose = log::log_enabled!(target: "xatlas", log::Level::Debug);
+        verbose
+    }
+
+    /// xatlas's print function is global. It is installed when the first atlas is created, and
+    /// whether verbose messages are printed is checked again every time an atlas is created,
+    /// so raising the log level later takes effect for the next atlas.
+    pub(crate) fn install() {
+        /// The verbosity xatlas was last configured with, `None` before the first atlas.
+        static INSTALLED: Mutex<Option<bool>> = Mutex::new(None);
+        let mut installed = INSTALLED.lock().unwrap_or_else(|e| e.into_inner());
+        let verbose = verbose();
+        if *installed != Some(verbose) {
+            unsafe { xatlas::SetPrint(Some(xatlas_rs_print), verbose) };
+            *installed = Some(verbose);
+        }
+    }
+}
+
+#[cfg(any(feature = "log", feature = "tracing"))]
+pub(crate) use self::print::install as install_print;
+
+#[cfg(not(any(feature = "log", feature = "tracing")))]
+pub(crate) fn install_print() {}
@@ -0,0 +1,290 @@
+//! Optional routing of xatlas's allocations through Rust's global allocator.
+//!
+//! xatlas's allocation functions are global, so the statistics cover every atlas in the process.
+//! Memory limits are per atlas: while an atlas with a limit runs an operation, it is the only
+//! atlas running one, and the allocations made meanwhile are charged to it.
+
+use crate::root::xatlas;
+use std::alloc::{self, Layout};
+use std::fmt;
+use std::os::raw::c_void;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// Bytes in front of every allocation that store its size and the `Usage` it is charged to.
+/// Also keeps the returned pointers aligned the way malloc would.
+const HEADER: usize = 16;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+/// The atlas that new allocations are charged to, null if none.
+static ACTIVE: AtomicPtr<Usage> = AtomicPtr::new(ptr::null_mut());
+
+/// Held shared by operations of atlases without a limit, and exclusively by operations of an
+/// atlas with one, so that `ACTIVE` is the only atlas allocating.
+static OPERATIONS: RwLock<()> = RwLock::new(());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Allocator {
+    /// No atlas was created yet, so the allocator can still be changed.
+    Undecided,
+    System,
+    Rust,
+}
+
+static ALLOCATOR: Mutex<Allocator> = Mutex::new(Allocator::Undecided);
+
+/// Memory allocated by xatlas, in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryStats {
+    pub current_bytes: usize,
+    pub peak_bytes: usize,
+}
+
+/// Returned by `use_rust_allocator` when xatlas has already been allocating from the C heap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AllocatorInUse;
+
+/// Routes all of xatlas's allocations through Rust's global allocator, which makes them visible
+/// to memory profilers and enables `memory_stats` and `Xatlas::set_memory_limit`.
+///
+/// Must be called before the first `Xatlas` is created, because memory xatlas allocated from the
+/// C heap can't be released through the Rust allocator.
+pub fn use_rust_allocator() -> Result<(), AllocatorInUse> {
+    let mut allocator = ALLOCATOR.lock().unwrap_or_else(PoisonError::into_inner);
+    match *allocator {
+        Allocator::Rust => Ok(()),
+        Allocator::System => Err(AllocatorInUse),
+        Allocator::Undecided => {
+            unsafe { xatlas::SetAlloc(Some(realloc), Some(free)) };
+            *allocator = Allocator::Rust;
+            Ok(())
+        }
+    }
+}
+
+/// Called before creating an atlas, after which the allocator can no longer change.
+pub(crate) fn lock_allocator() {
+    let mut allocator = ALLOCATOR.lock().unwrap_or_else(PoisonError::into_inner);
+    if *allocator == Allocator::Undecided {
+        *allocator = Allocator::System;
+    }
+}
+
+/// Current and peak memory allocated by all atlases, or `None` if xatlas doesn't use the
+/// Rust allocator. See `use_rust_allocator`.
+pub fn memory_stats() -> Option<MemoryStats> {
+    let allocator = *ALLOCATOR.lock().unwrap_or_else(PoisonError::into_inner);
+    if allocator != Allocator::Rust {
+        return None;
+    }
+    Some(MemoryStats {
+        current_bytes: CURRENT.load(Ordering::Relaxed),
+        peak_bytes: PEAK.load(Ordering::Relaxed),
+    })
+}
+
+pub(crate) fn is_rust_allocator() -> bool {
+    *ALLOCATOR.lock().unwrap_or_else(PoisonError::into_inner) == Allocator::Rust
+}
+
+/// Memory charged to one atlas, shared by the atlas and its progress state. The allocation
+/// headers point to it, so it must outlive the atlas's `xatlas::Destroy`.
+#[derive(Debug)]
+pub(crate) struct Usage {
+    /// `usize::MAX` if the atlas has no limit.
+    limit: AtomicUsize,
+    allocated: AtomicUsize,
+    /// Set by the allocator when `allocated` went over `limit`, cleared by `begin`.
+    exceeded: AtomicBool,
+}
+
+impl Default for Usage {
+    fn default() -> Self {
+        Self {
+            limit: AtomicUsize::new(usize::MAX),
+            allocated: AtomicUsize::new(0),
+            exceeded: AtomicBool::new(false),
+        }
+    }
+}
+
+impl Usage {
+    pub(crate) fn set_limit(&self, limit: Option<usize>) {
+        self.limit
+            .store(limit.unwrap_or(usize::MAX), Ordering::Relaxed);
+    }
+
+    fn has_limit(&self) -> bool {
+        self.limit.load(Ordering::Relaxed) != usize::MAX
+    }
+
+    /// Whether the limit was exceeded since the current operation began.
+    pub(crate) fn is_exceeded(&self) -> bool {
+        self.exceeded.load(Ordering::Relaxed)
+    }
+
+    /// Starts an operation of the atlas that calls into xatlas. With a limit, waits for the
+    /// operations of all other atlases to finish and charges the allocations to this atlas until
+    /// the returned guard is dropped, otherwise only waits for atlases with a limit.
+    ///
+    /// Operations of different atlases must not be nested, e.g. from a progress callback, since
+    /// that deadlocks.
+    pub(crate) fn begin(&self) -> Operation {
+        let mut operation = Operation {
+            _shared: None,
+            exclusive: None,
+        };
+        if !is_rust_allocator() {
+            return operation;
+        }
+        if !self.has_limit() {
+            operation._shared = Some(OPERATIONS.read().unwrap_or_else(PoisonError::into_inner));
+            return operation;
+        }
+        operation.exclusive = Some(OPERATIONS.write().unwrap_or_else(PoisonError::into_inner));
+        self.exceeded.store(
+            self.allocated.load(Ordering::Relaxed) > self.limit.load(Ordering::Relaxed),
+            Ordering::Relaxed,
+        );
+        ACTIVE.store(self as *const Usage as *mut Usage, Ordering::Relaxed);
+        operation
+    }
+
+    fn add(&self, size: usize) {
+        let allocated = self.allocated.fetch_add(size, Ordering::Relaxed) + size;
+        if allocated > self.limit.load(Ordering::Relaxed) {
+            self.exceeded.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn sub(&self, size: usize) {
+        self.allocated.fetch_sub(size, Ordering::Relaxed);
+    }
+}
+
+/// Returned by `Usage::begin`.
+pub(crate) struct Operation {
+    _shared: Option<RwLockReadGuard<'static, ()>>,
+    exclusive: Option<RwLockWriteGuard<'static, ()>>,
+}
+
+impl Drop for Operation {
+    fn drop(&mut self) {
+        // Runs before the lock is released.
+        if self.exclusive.is_some() {
+            ACTIVE.store(ptr::null_mut(), Ordering::Relaxed);
+        }
+    }
+}
+
+fn layout(size: usize) -> Option<Layout> {
+    Layout::from_size_align(size.checked_add(HEADER)?, HEADER).ok()
+}
+
+/// The size and owner stored in front of an allocation. The owner is null if the allocation
+/// wasn't made during an operation of an atlas with a limit.
+unsafe fn read_header(base: *const u8) -> (usize, *const Usage) {
+    (
+        *(base as *const usize),
+        *(base as *const *const Usage).add(1),
+    )
+}
+
+unsafe extern "C" fn realloc(data: *mut c_void, size: usize) -> *mut c_void {
+    if size == 0 {
+        free(data);
+        return ptr::null_mut();
+    }
+    let new_layout = match layout(size) {
+        None => return ptr::null_mut(),
+        Some(layout) => layout,
+    };
+
+    let (base, old_size, old_owner) = if data.is_null() {
+        (alloc::alloc(new_layout), 0, ptr::null())
+    } else {
+        let base = (data as *mut u8).sub(HEADER);
+        let (old_size, old_owner) = read_header(base);
+        let old_layout = Layout::from_size_align_unchecked(old_size + HEADER, HEADER);
+        (
+            alloc::realloc(base, old_layout, new_layout.size()),
+            old_size,
+            old_owner,
+        )
+    };
+    if base.is_null() {
+        return ptr::null_mut();
+    }
+
+    let owner = ACTIVE.load(Ordering::Relaxed) as *const Usage;
+    if let Some(old_owner) = old_owner.as_ref() {
+        old_owner.sub(old_size);
+    }
+    if let Some(owner) = owner.as_ref() {
+        owner.add(size);
+    }
+    *(base as *mut usize) = size;
+    *(base as *mut *const Usage).add(1) = owner;
+    let current = if size >= old_size {
+        CURRENT.fetch_add(size - old_size, Ordering::Relaxed) + size - old_size
+    } else {
+        CURRENT.fetch_sub(old_size - size, Ordering::Relaxed) - (old_size - size)
+    };
+    PEAK.fetch_max(current, Ordering::Relaxed);
+
+    base.add(HEADER) as *mut c_void
+}
+
+unsafe extern "C" fn free(data: *mut c_void) {
+    if data.is_null() {
+        return;
+    }
+    let base = (data as *mut u8).sub(HEADER);
+    let (size, owner) = read_header(base);
+    if let Some(owner) = owner.as_ref() {
+        owner.sub(size);
+    }
+    CURRENT.fetch_sub(size, Ordering::Relaxed);
+    alloc::dealloc(
+        base,
+        Layout::from_size_align_unchecked(size + HEADER, HEADER),
+    );
+}
+
+impl fmt::Display for AllocatorInUse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("xatlas is already allocating from the C heap")
+    }
+}
+
+impl std::error::Error for AllocatorInUse {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn charges_allocations_to_the_active_atlas() {
+        let usage = Usage::default();
+        usage.set_limit(Some(100));
+        unsafe {
+            let untracked = realloc(ptr::null_mut(), 200);
+            ACTIVE.store(&usage as *const Usage as *mut Usage, Ordering::Relaxed);
+            let data = realloc(ptr::null_mut(), 60);
+            assert_eq!(usage.allocated.load(Ordering::Relaxed), 60);
+            assert!(!usage.is_exceeded());
+
+            let data = realloc(data, 120);
+            assert_eq!(usage.allocated.load(Ordering::Relaxed), 120);
+            assert!(usage.is_exceeded());
+            ACTIVE.store(ptr::null_mut(), Ordering::Relaxed);
+
+            free(data);
+            free(untracked);
+        }
+        assert_eq!(usage.allocated.load(Ordering::Relaxed), 0);
+    }
+}
@@ -0,0 +1,436 @@
+//! UV distortion metrics, computed from the input positions and the output UVs.
+//!
+//! Stretch follows Sander et al., "Texture Mapping Progressive Meshes": it measures how much a
+//! unit length on the surface is stretched in UV space, normalized so that a parameterization
+//! that only scales the surface uniformly has a stretch of 1.
+
+use crate::{AtlasOutput, Chart, ChartType, Mesh, MeshDecl};
+
+/// Triangles with a smaller area, in world units or texels, are counted as degenerate and left
+/// out of the distortion averages.
+const DEGENERATE_AREA: f64 = 1e-12;
+
+/// Distortion of a single output triangle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TriangleMetrics {
+    /// Area of the input triangle, in world units.
+    pub area: f32,
+    /// Area of the triangle in the atlas, in texels.
+    pub uv_area: f32,
+    /// Largest and smallest singular value of the mapping from the surface to the atlas, in
+    /// texels per world unit. Equal values mean the triangle is only scaled, not distorted.
+    pub sigma_max: f32,
+    pub sigma_min: f32,
+    /// Largest difference between an angle of the input triangle and the same angle in the
+    /// atlas, in radians.
+    pub angle_distortion: f32,
+    /// The UV winding differs from the majority of the chart's triangles.
+    pub flipped: bool,
+    /// The triangle has no area on the surface or in the atlas.
+    pub degenerate: bool,
+    /// Index into `Mesh::chart_array`, `None` for ignored faces.
+    pub chart: Option<u32>,
+}
+
+/// Distortion aggregated over a set of triangles. Averages are weighted by surface area and
+/// skip degenerate triangles.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct DistortionMetrics {
+    /// Sander's L2 (RMS) stretch. 1 is optimal.
+    pub l2_stretch: f32,
+    /// Sander's L∞ stretch, the worst stretch in any direction of any triangle. 1 is optimal.
+    pub linf_stretch: f32,
+    /// Average and largest `TriangleMetrics::angle_distortion`, in radians.
+    pub angle_distortion: f32,
+    pub max_angle_distortion: f32,
+    /// Average factor by which a triangle's share of UV space differs from its share of the
+    /// surface, `max(r, 1 / r)`. 1 is optimal.
+    pub area_distortion: f32,
+    /// Average and largest MIPS energy, `sigma_max / sigma_min + sigma_min / sigma_max`.
+    /// 2 is optimal, i.e. conformal.
+    pub mips: f32,
+    pub max_mips: f32,
+    pub triangle_count: u32,
+    pub flipped_triangle_count: u32,
+    pub degenerate_triangle_count: u32,
+    /// Surface area in world units.
+    pub area: f32,
+    /// Atlas area in texels.
+    pub uv_area: f32,
+}
+
+/// Metrics of one `Chart`, in the order of `Mesh::chart_array`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChartMetrics {
+    pub atlas_index: u32,
+    pub type_: ChartType,
+    pub distortion: DistortionMetrics,
+}
+
+/// Metrics of one output `Mesh`. Triangles excluded through `MeshDecl::face_ignore_data` only
+/// appear in `triangles`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeshMetrics {
+    /// One entry per triangle of `Mesh::index_array`.
+    pub triangles: Vec<TriangleMetrics>,
+    pub charts: Vec<ChartMetrics>,
+    pub distortion: DistortionMetrics,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AtlasMetrics {
+    pub meshes: Vec<MeshMetrics>,
+    pub distortion: DistortionMetrics,
+}
+
+impl AtlasMetrics {
+    /// Computes the metrics of every output mesh from its input, see [`AtlasOutput::meshes`].
+    ///
+    /// # Panics
+    ///
+    /// If `inputs` doesn't match the output meshes.
+    pub fn compute(inputs: &[MeshDecl<'_>], output: &AtlasOutput) -> Self {
+        Self::from_meshes(inputs, &output.meshes)
+    }
+
+    /// `compute` for meshes that are still borrowed from the atlas, see `Xatlas::meshes`.
+    pub(crate) fn from_meshes(inputs: &[MeshDecl<'_>], meshes: &[Mesh<'_>]) -> Self {
+        assert_eq!(
+            inputs.len(),
+            meshes.len(),
+            "one input is needed per output mesh"
+        );
+        let meshes: Vec<MeshMetrics> = inputs
+            .iter()
+            .zip(meshes)
+            .map(|(input, mesh)| MeshMetrics::compute(input, mesh))
+            .collect();
+        let distortion = aggregate(meshes.iter().flat_map(MeshMetrics::charted_triangles));
+        AtlasMetrics { meshes, distortion }
+    }
+}
+
+impl MeshMetrics {
+    /// Computes the metrics of a single output mesh from the declaration it was added with.
+    pub fn compute(input: &MeshDecl<'_>, mesh: &Mesh<'_>) -> Self {
+        let mut triangles: Vec<TriangleMetrics> = mesh
+            .index_array
+            .chunks_exact(3)
+            .map(|face| {
+                let vertices = [0, 1, 2].map(|i| &mesh.vertex_array[face[i] as usize]);
+                let positions = vertices.map(|v| input.vertex_position_data.read(v.xref, 3));
+                triangle_metrics(positions, vertices.map(|v| v.uv))
+            })
+            .collect();
+
+        for (chart_index, chart) in mesh.chart_array.iter().enumerate() {
+            for &face in chart.face_array.iter() {
+                triangles[face as usize].chart = Some(chart_index as u32);
+            }
+            for face in flipped_faces(mesh, chart) {
+                triangles[face as usize].flipped = true;
+            }
+        }
+
+        let charts = mesh
+            .chart_array
+            .iter()
+            .map(|chart| ChartMetrics {
+                atlas_index: chart.atlas_index,
+                type_: chart.type_,
+                distortion: aggregate(
+                    chart
+                        .face_array
+                        .iter()
+                        .map(|&face| &triangles[face as usize]),
+                ),
+            })
+            .collect();
+
+        let mut metrics = MeshMetrics {
+            triangles,
+            charts,
+            distortion: DistortionMetrics::default(),
+        };
+        metrics.distortion = aggregate(metrics.charted_triangles());
+        metrics
+    }
+
+    /// Triangles that belong to a chart, which excludes ignored faces.
+    fn charted_triangles(&self) -> impl Iterator<Item = &TriangleMetrics> + Clone {
+        self.triangles
+            .iter()
+            .filter(|triangle| triangle.chart.is_some())
+    }
+}
+
+/// Faces of `chart` whose UV winding differs from the orientation most of the chart's UV area
+/// has, the triangles `TriangleMetrics::flipped` reports.
+pub(crate) fn flipped_faces<'a>(
+    mesh: &'a Mesh<'_>,
+    chart: &'a Chart<'_>,
+) -> impl Iterator<Item = u32> + 'a {
+    let signed_area: f64 = chart
+        .face_array
+        .iter()
+        .map(|&face| signed_uv_area(mesh, face))
+        .sum();
+    chart
+        .face_array
+        .iter()
+        .copied()
+        .filter(move |&face| signed_uv_area(mesh, face) * signed_area < 0.0)
+}
+
+fn signed_uv_area(mesh: &Mesh<'_>, face: u32) -> f64 {
+    let uv = |i: usize| {
+        let index = mesh.index_array[face as usize * 3 + i];
+        mesh.vertex_array[index as usize].uv.map(f64::from)
+    };
+    let (a, b, c) = (uv(0), uv(1), uv(2));
+    ((b[0] - a[0]) * (c[1] - a[1]) - (c[0] - a[0]) * (b[1] - a[1])) / 2.0
+}
+
+fn triangle_metrics(positions: [[f32; 3]; 3], uvs: [[f32; 2]; 3]) -> TriangleMetrics {
+    let p = positions.map(|p| p.map(f64::from));
+    let t = uvs.map(|uv| uv.map(f64::from));
+
+    // Place the input triangle in its own plane, with the first edge along the x axis.
+    let e1 = sub3(p[1], p[0]);
+    let e2 = sub3(p[2], p[0]);
+    let e1_length = dot3(e1, e1).sqrt();
+    let cross = cross3(e1, e2);
+    let area = dot3(cross, cross).sqrt() / 2.0;
+    let uv_area = ((t[1][0] - t[0][0]) * (t[2][1] - t[0][1])
+        - (t[2][0] - t[0][0]) * (t[1][1] - t[0][1]))
+        / 2.0;
+
+    if area < DEGENERATE_AREA || uv_area.abs() < DEGENERATE_AREA {
+        return TriangleMetrics {
+            area: area as f32,
+            uv_area: uv_area.abs() as f32,
+            sigma_max: 0.0,
+            sigma_min: 0.0,
+            angle_distortion: 0.0,
+            flipped: false,
+            degenerate: true,
+            chart: None,
+        };
+    }
+
+    let x2 = e1_length;
+    let x3 = dot3(e2, e1) / e1_length;
+    let y3 = 2.0 * area / e1_length;
+
+    // Jacobian of the map from the plane to UV space: [du dv] * inverse([x2 x3; 0 y3]).
+    let du = [t[1][0] - t[0][0], t[2][0] - t[0][0]];
+    let dv = [t[1][1] - t[0][1], t[2][1] - t[0][1]];
+    let a = du[0] / x2;
+    let b = (du[1] - a * x3) / y3;
+    let c = dv[0] / x2;
+    let d = (dv[1] - c * x3) / y3;
+
+    // Closed form singular values of a 2x2 matrix.
+    let q = (((a + d) / 2.0).powi(2) + ((c - b) / 2.0).powi(2)).sqrt();
+    let r = (((a - d) / 2.0).powi(2) + ((c + b) / 2.0).powi(2)).sqrt();
+    let sigma_max = q + r;
+    let sigma_min = (q - r).abs();
+
+    let angle_distortion = (0..3)
+        .map(|i| {
+            let corner =
+                |v: [f64; 3], prev: [f64; 3], next: [f64; 3]| angle(sub3(prev, v), sub3(next, v));
+            let (prev, next) = ((i + 2) % 3, (i + 1) % 3);
+            let surface = corner(p[i], p[prev], p[next]);
+            let lift = |uv: [f64; 2]| [uv[0], uv[1], 0.0];
+            let atlas = corner(lift(t[i]), lift(t[prev]), lift(t[next]));
+            (surface - atlas).abs()
+        })
+        .fold(0.0, f64::max);
+
+    TriangleMetrics {
+        area: area as f32,
+        uv_area: uv_area.abs() as f32,
+        sigma_max: sigma_max as f32,
+        sigma_min: sigma_min as f32,
+        angle_distortion: angle_distortion as f32,
+        flipped: false,
+        degenerate: false,
+        chart: None,
+    }
+}
+
+fn aggregate<'a>(
+    triangles: impl Iterator<Item = &'a TriangleMetrics> + Clone,
+) -> DistortionMetrics {
+    let mut metrics = DistortionMetrics::default();
+    let (mut area, mut uv_area) = (0.0f64, 0.0f64);
+    for triangle in triangles.clone() {
+        metrics.triangle_count += 1;
+        metrics.flipped_triangle_count += triangle.flipped as u32;
+        metrics.area += triangle.area;
+        metrics.uv_area += triangle.uv_area;
+        if triangle.degenerate {
+            metrics.degenerate_triangle_count += 1;
+        } else {
+            area += triangle.area as f64;
+            uv_area += triangle.uv_area as f64;
+        }
+    }
+    if area <= 0.0 || uv_area <= 0.0 {
+        return metrics;
+    }
+
+    // Scales stretch and area ratios so that uniformly scaling the whole set is optimal.
+    let scale = (uv_area / area).sqrt();
+    let (mut l2_sum, mut linf) = (0.0f64, 0.0f64);
+    let (mut angle_sum, mut area_sum, mut mips_sum) = (0.0f64, 0.0f64, 0.0f64);
+    for triangle in triangles.filter(|triangle| !triangle.degenerate) {
+        let weight = triangle.area as f64 / area;
+        // Stretch is defined on the inverse map, from UV space to the surface.
+        let (gamma_max, gamma_min) = (
+            1.0 / triangle.sigma_min as f64,
+            1.0 / triangle.sigma_max as f64,
+        );
+        l2_sum += weight * (gamma_max * gamma_max + gamma_min * gamma_min) / 2.0;
+        linf = linf.max(gamma_max);
+
+        angle_sum += weight * triangle.angle_distortion as f64;
+        metrics.max_angle_distortion = metrics.max_angle_distortion.max(triangle.angle_distortion);
+
+        let ratio = (triangle.uv_area as f64 / triangle.area as f64) / (scale * scale);
+        area_sum += weight * ratio.max(1.0 / ratio);
+
+        let mips = (triangle.sigma_max / triangle.sigma_min
+            + triangle.sigma_min / triangle.sigma_max) as f64;
+        mips_sum += weight * mips;
+        metrics.max_mips = metrics.max_mips.max(mips as f32);
+    }
+
+    metrics.l2_stretch = (l2_sum.sqrt() * scale) as f32;
+    metrics.linf_stretch = (linf * scale) as f32;
+    metrics.angle_distortion = angle_sum as f32;
+    metrics.area_distortion = area_sum as f32;
+    metrics.mips = mips_sum as f32;
+    metrics
+}
+
+fn angle(a: [f64; 3], b: [f64; 3]) -> f64 {
+    let cross = cross3(a, b);
+    dot3(cross, cross).sqrt().atan2(dot3(a, b))
+}
+
+fn sub3(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot3(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross3(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Vertex;
+    use std::borrow::Cow;
+
+    /// A unit right triangle on the surface.
+    const RIGHT_TRIANGLE: [[f32; 3]; 3] = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+
+    /// One chart holding a copy of `RIGHT_TRIANGLE` for every UV triangle.
+    fn mesh(triangles: &[[[f32; 2]; 3]]) -> Mesh<'static> {
+        let mut vertex_array = Vec::new();
+        for triangle in triangles {
+            for (xref, &uv) in triangle.iter().enumerate() {
+                vertex_array.push(Vertex {
+                    atlas_index: 0,
+                    chart_index: 0,
+                    uv,
+                    xref: xref as u32,
+                });
+            }
+        }
+        Mesh {
+            index_array: Cow::Owned((0..vertex_array.len() as u32).collect()),
+            chart_array: vec![Chart {
+                face_array: Cow::Owned((0..triangles.len() as u32).collect()),
+                atlas_index: 0,
+                type_: ChartType::Planar,
+                material: 0,
+            }],
+            vertex_array,
+        }
+    }
+
+    fn metrics(triangles: &[[[f32; 2]; 3]]) -> MeshMetrics {
+        MeshMetrics::compute(&MeshDecl::new(&RIGHT_TRIANGLE[..]), &mesh(triangles))
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-5,
+            "{} is not {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn uniform_scaling_is_optimal() {
+        let metrics = metrics(&[[[0.0, 0.0], [4.0, 0.0], [0.0, 4.0]]]);
+        let triangle = metrics.triangles[0];
+        assert_close(triangle.sigma_max, 4.0);
+        assert_close(triangle.sigma_min, 4.0);
+
+        let distortion = metrics.distortion;
+        assert_close(distortion.l2_stretch, 1.0);
+        assert_close(distortion.linf_stretch, 1.0);
+        assert_close(distortion.mips, 2.0);
+        assert_close(distortion.angle_distortion, 0.0);
+        assert_close(distortion.area_distortion, 1.0);
+        assert_close(distortion.uv_area, 8.0);
+    }
+
+    #[test]
+    fn anisotropic_stretch_matches_sander() {
+        // Twice as wide in UV space as on the surface: singular values 2 and 1. Normalized to the
+        // same area, the inverse map stretches by 1 / sqrt(2) and sqrt(2), so
+        // L2 = sqrt((1/2 + 2) / 2) and Linf = sqrt(2).
+        let metrics = metrics(&[[[0.0, 0.0], [2.0, 0.0], [0.0, 1.0]]]);
+        let triangle = metrics.triangles[0];
+        assert_close(triangle.sigma_max, 2.0);
+        assert_close(triangle.sigma_min, 1.0);
+
+        let distortion = metrics.distortion;
+        assert_close(distortion.l2_stretch, 1.25f32.sqrt());
+        assert_close(distortion.linf_stretch, 2.0f32.sqrt());
+        assert_close(distortion.mips, 2.5);
+        assert!(distortion.angle_distortion > 0.0);
+    }
+
+    #[test]
+    fn a_triangle_against_the_chart_winding_is_flipped() {
+        let triangles = [
+            [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]],
+            [[2.0, 0.0], [3.0, 0.0], [2.0, 1.0]],
+            [[4.0, 0.0], [4.0, 1.0], [5.0, 0.0]],
+        ];
+        let mesh = mesh(&triangles);
+        let faces: Vec<u32> = flipped_faces(&mesh, &mesh.chart_array[0]).collect();
+        assert_eq!(faces, [2]);
+
+        let metrics = metrics(&triangles);
+        let flipped: Vec<bool> = metrics.triangles.iter().map(|t| t.flipped).collect();
+        assert_eq!(flipped, [false, false, true]);
+        assert_eq!(metrics.distortion.flipped_triangle_count, 1);
+        assert_eq!(metrics.charts[0].distortion.flipped_triangle_count, 1);
+    }
+}
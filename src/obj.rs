@@ -0,0 +1,647 @@
+//! Wavefront OBJ / MTL import, and export of the unwrapped meshes.
+//!
+//! ```no_run
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let obj = xatlas_rs::obj::ObjMesh::load("model.obj")?;
+//! let mut atlas = xatlas_rs::Xatlas::new();
+//! atlas.add_mesh(&obj.mesh_decl())?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{AtlasOutput, LoadError, MeshDecl};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// An OBJ file with its vertex attributes unified into a single index buffer, since xatlas
+/// doesn't support separate position, UV and normal indices.
+#[derive(Debug, Clone, Default)]
+pub struct ObjMesh {
+    pub positions: Vec<[f32; 3]>,
+    /// Empty if the file doesn't have normals.
+    pub normals: Vec<[f32; 3]>,
+    /// Empty if the file doesn't have texture coordinates.
+    pub uvs: Vec<[f32; 2]>,
+    pub indices: Vec<u32>,
+    /// Number of vertices of every face.
+    pub face_vertex_counts: Vec<u8>,
+    /// Index into `materials` for every face.
+    pub face_materials: Vec<u32>,
+    /// Materials in order of their first `usemtl`. Faces before the first `usemtl` use a
+    /// material with an empty name.
+    pub materials: Vec<ObjMaterial>,
+    /// Names of the files referenced by `mtllib`.
+    pub material_libraries: Vec<String>,
+    /// For every vertex, the index of the `v` statement it came from.
+    pub position_indices: Vec<u32>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ObjMaterial {
+    pub name: String,
+    /// `Kd`
+    pub diffuse: Option<[f32; 3]>,
+    /// `map_Kd`
+    pub diffuse_texture: Option<String>,
+}
+
+pub type ObjError = LoadError;
+
+/// How `write` splits the faces of every mesh into OBJ groups.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ObjGroups {
+    /// One object per mesh, without groups.
+    #[default]
+    None,
+    /// A group per atlas page, named `atlas_<index>`.
+    Atlas,
+    /// A group per chart, named `chart_<index>`.
+    Chart,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ObjWriteOptions {
+    pub groups: ObjGroups,
+}
+
+/// Looks up or creates the vertex for a `v/vt/vn` triple.
+#[derive(Default)]
+struct VertexCache {
+    vertices: HashMap<(u32, Option<u32>, Option<u32>), u32>,
+}
+
+impl ObjMesh {
+    /// Loads an OBJ file and the MTL files it references, which are looked up relative to the
+    /// OBJ file. Missing MTL files are ignored, the materials then only have a name.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ObjError> {
+        let path = path.as_ref();
+        let mut mesh = Self::parse(BufReader::new(File::open(path)?))?;
+
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+        for library in mesh.material_libraries.clone() {
+            match File::open(directory.join(&library)) {
+                Ok(file) => mesh.parse_mtl(BufReader::new(file))?,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Ok(mesh)
+    }
+
+    /// Parses OBJ data. Materials referenced by `mtllib` aren't loaded, see `parse_mtl`.
+    pub fn parse(reader: impl BufRead) -> Result<Self, ObjError> {
+        let mut mesh = ObjMesh::default();
+        let mut positions = Vec::new();
+        let mut uvs = Vec::new();
+        let mut normals = Vec::new();
+        let mut corners = Vec::new();
+        let mut cache = VertexCache::default();
+        let mut material_indices = HashMap::new();
+        let mut current_material = None;
+
+        for (line_index, line) in logical_lines(reader) {
+            let line = line?;
+            let line_number = line_index + 1;
+            let error = |message: String| ObjError::Parse(format!("line {line_number}: {message}"));
+
+            let line = line.split('#').next().unwrap_or("");
+            let mut tokens = line.split_whitespace();
+            let keyword = match tokens.next() {
+                None => continue,
+                Some(keyword) => keyword,
+            };
+
+            match keyword {
+                "v" => positions.push(parse_floats::<3>(tokens, 3).map_err(error)?),
+                "vt" => uvs.push(parse_floats::<2>(tokens, 1).map_err(error)?),
+                "vn" => normals.push(parse_floats::<3>(tokens, 3).map_err(error)?),
+                "f" => {
+                    corners.clear();
+                    for token in tokens {
+                        corners.push(
+                            parse_corner(token, positions.len(), uvs.len(), normals.len())
+                                .map_err(error)?,
+                        );
+                    }
+                    if corners.len() < 3 || corners.len() > u8::MAX as usize {
+                        return Err(error(format!(
+                            "face has {} vertices, expected between 3 and {}",
+                            corners.len(),
+                            u8::MAX
+                        )));
+                    }
+
+                    let material = match current_material {
+                        Some(material) => material,
+                        None => *material_indices
+                            .entry(String::new())
+                            .or_insert_with(|| mesh.add_material(String::new())),
+                    };
+                    current_material = Some(material);
+
+                    for &corner in &corners {
+                        let index = cache.vertex(&mut mesh, corner, &positions, &uvs, &normals);
+                        mesh.indices.push(index);
+                    }
+                    mesh.face_vertex_counts.push(corners.len() as u8);
+                    mesh.face_materials.push(material);
+                }
+                "usemtl" => {
+                    let name = tokens.collect::<Vec<_>>().join(" ");
+                    let material = *material_indices
+                        .entry(name.clone())
+                        .or_insert_with(|| mesh.add_material(name));
+                    current_material = Some(material);
+                }
+                "mtllib" => mesh.material_libraries.extend(tokens.map(str::to_owned)),
+                // Groups, objects, smoothing groups and everything else don't affect the mesh.
+                _ => {}
+            }
+        }
+
+        // Attributes only some corners had are kept, the other vertices get zeros.
+        if !cache.has_uvs() {
+            mesh.uvs.clear();
+        }
+        if !cache.has_normals() {
+            mesh.normals.clear();
+        }
+
+        Ok(mesh)
+    }
+
+    /// Parses MTL data, filling in the properties of materials used by the mesh.
+    /// Materials the mesh doesn't use are ignored.
+    pub fn parse_mtl(&mut self, reader: impl BufRead) -> Result<(), ObjError> {
+        let mut current = None;
+        for (line_index, line) in logical_lines(reader) {
+            let line = line?;
+            let error =
+                |message: String| ObjError::Parse(format!("line {}: {message}", line_index + 1));
+
+            let line = line.split('#').next().unwrap_or("");
+            let mut tokens = line.split_whitespace();
+            let keyword = match tokens.next() {
+                None => continue,
+                Some(keyword) => keyword,
+            };
+
+            match keyword {
+                "newmtl" => {
+                    let name = tokens.collect::<Vec<_>>().join(" ");
+                    current = self.materials.iter().position(|m| m.name == name);
+                }
+                "Kd" => {
+                    let diffuse = parse_floats::<3>(tokens, 3).map_err(error)?;
+                    if let Some(material) = current.map(|i| &mut self.materials[i]) {
+                        material.diffuse = Some(diffuse);
+                    }
+                }
+                "map_Kd" => {
+                    // Options such as -s come before the file name.
+                    let texture = tokens.last().map(str::to_owned);
+                    if let Some(material) = current.map(|i| &mut self.materials[i]) {
+                        material.diffuse_texture = texture;
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn face_count(&self) -> usize {
+        self.face_vertex_counts.len()
+    }
+
+    /// Declaration for `Xatlas::add_mesh`. Face vertex counts are only passed when the mesh has
+    /// faces that aren't triangles, and materials only when there is more than one.
+    pub fn mesh_decl(&self) -> MeshDecl<'_> {
+        MeshDecl {
+            face_material_data: (self.materials.len() > 1).then_some(&self.face_materials[..]),
+            ..MeshDecl::from_polygons(
+                &self.positions,
+                &self.normals,
+                &self.uvs,
+                &self.indices,
+                &self.face_vertex_counts,
+            )
+        }
+    }
+
+    fn add_material(&mut self, name: String) -> u32 {
+        self.materials.push(ObjMaterial {
+            name,
+            ..ObjMaterial::default()
+        });
+        self.materials.len() as u32 - 1
+    }
+}
+
+impl VertexCache {
+    fn vertex(
+        &mut self,
+        mesh: &mut ObjMesh,
+        corner: (u32, Option<u32>, Option<u32>),
+        positions: &[[f32; 3]],
+        uvs: &[[f32; 2]],
+        normals: &[[f32; 3]],
+    ) -> u32 {
+        *self.vertices.entry(corner).or_insert_with(|| {
+            let (position, uv, normal) = corner;
+            mesh.positions.push(positions[position as usize]);
+            mesh.uvs.push(uv.map_or([0.0; 2], |uv| uvs[uv as usize]));
+            mesh.normals
+                .push(normal.map_or([0.0; 3], |normal| normals[normal as usize]));
+            mesh.position_indices.push(position);
+            mesh.positions.len() as u32 - 1
+        })
+    }
+
+    fn has_uvs(&self) -> bool {
+        self.vertices.keys().any(|&(_, uv, _)| uv.is_some())
+    }
+
+    fn has_normals(&self) -> bool {
+        self.vertices.keys().any(|&(_, _, normal)| normal.is_some())
+    }
+}
+
+/// Writes the unwrapped meshes as OBJ. Positions and normals are taken from `inputs`, see
+/// [`AtlasOutput::meshes`], and the atlas UVs are normalized to the 0-1 range and written as `vt`.
+/// Faces of ignored vertices go into an `ignored` group when grouping.
+pub fn write(
+    mut writer: impl Write,
+    inputs: &[MeshDecl<'_>],
+    output: &AtlasOutput,
+    options: &ObjWriteOptions,
+) -> io::Result<()> {
+    if inputs.len() != output.meshes.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "{} input meshes for {} output meshes",
+                inputs.len(),
+                output.meshes.len()
+            ),
+        ));
+    }
+
+    let scale = [
+        1.0 / output.width.max(1) as f32,
+        1.0 / output.height.max(1) as f32,
+    ];
+    let mut base = 1;
+    for (mesh_index, (input, mesh)) in inputs.iter().zip(&output.meshes).enumerate() {
+        let input_vertex_count = input.vertex_position_data.count(3);
+        if mesh
+            .vertex_array
+            .iter()
+            .any(|v| v.xref >= input_vertex_count)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("input mesh {mesh_index} has fewer vertices than its output references"),
+            ));
+        }
+        if let Some(normals) = &input.vertex_normal_data {
+            let normal_count = normals.count(3);
+            if normal_count != input_vertex_count {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "input mesh {mesh_index} has {normal_count} normals for \
+                         {input_vertex_count} vertices"
+                    ),
+                ));
+            }
+        }
+
+        writeln!(writer, "o mesh_{mesh_index}")?;
+        for vertex in &mesh.vertex_array {
+            let [x, y, z] = input.vertex_position_data.read(vertex.xref, 3);
+            writeln!(writer, "v {x} {y} {z}")?;
+        }
+        for vertex in &mesh.vertex_array {
+            let [u, v] = vertex.uv;
+            writeln!(writer, "vt {} {}", u * scale[0], v * scale[1])?;
+        }
+        if let Some(normals) = &input.vertex_normal_data {
+            for vertex in &mesh.vertex_array {
+                let [x, y, z] = normals.read(vertex.xref, 3);
+                writeln!(writer, "vn {x} {y} {z}")?;
+            }
+        }
+
+        let mut groups = BTreeMap::<Option<i32>, Vec<&[u32]>>::new();
+        for face in mesh.index_array.chunks_exact(3) {
+            let vertex = &mesh.vertex_array[face[0] as usize];
+            let key = match options.groups {
+                ObjGroups::None => Some(0),
+                _ if vertex.is_ignored() => None,
+                ObjGroups::Atlas => Some(vertex.atlas_index),
+                ObjGroups::Chart => Some(vertex.chart_index),
+            };
+            groups.entry(key).or_default().push(face);
+        }
+
+        let has_normals = input.vertex_normal_data.is_some();
+        for (key, faces) in groups {
+            match (options.groups, key) {
+                (ObjGroups::None, _) => {}
+                (_, None) => writeln!(writer, "g ignored")?,
+                (ObjGroups::Atlas, Some(index)) => writeln!(writer, "g atlas_{index}")?,
+                (ObjGroups::Chart, Some(index)) => writeln!(writer, "g chart_{index}")?,
+            }
+            for face in faces {
+                write!(writer, "f")?;
+                for &index in face {
+                    let index = base + index as usize;
+                    if has_normals {
+                        write!(writer, " {index}/{index}/{index}")?;
+                    } else {
+                        write!(writer, " {index}/{index}")?;
+                    }
+                }
+                writeln!(writer)?;
+            }
+        }
+
+        base += mesh.vertex_array.len();
+    }
+
+    writer.flush()
+}
+
+/// Like `write`, but creates the file at `path`.
+pub fn save(
+    path: impl AsRef<Path>,
+    inputs: &[MeshDecl<'_>],
+    output: &AtlasOutput,
+    options: &ObjWriteOptions,
+) -> io::Result<()> {
+    write(BufWriter::new(File::create(path)?), inputs, output, options)
+}
+
+/// Lines with a trailing backslash continue on the next line. Yields the index of the
+/// first physical line of each logical line.
+fn logical_lines(reader: impl BufRead) -> impl Iterator<Item = (usize, io::Result<String>)> {
+    let mut lines = reader.lines().enumerate();
+    std::iter::from_fn(move || {
+        let (index, line) = lines.next()?;
+        let mut line = match line {
+            Ok(line) => line,
+            Err(e) => return Some((index, Err(e))),
+        };
+        while line.ends_with('\\') {
+            line.pop();
+            match lines.next() {
+                Some((_, Ok(next))) => line.push_str(&next),
+                Some((_, Err(e))) => return Some((index, Err(e))),
+                None => break,
+            }
+        }
+        Some((index, Ok(line)))
+    })
+}
+
+/// Parses up to `N` floats, of which at least `required` must be present.
+/// Extra values such as `w` or vertex colors are ignored.
+fn parse_floats<'a, const N: usize>(
+    tokens: impl Iterator<Item = &'a str>,
+    required: usize,
+) -> Result<[f32; N], String> {
+    let mut out = [0.0; N];
+    let mut count = 0;
+    for (value, token) in out.iter_mut().zip(tokens) {
+        *value = token
+            .parse()
+            .map_err(|_| format!("invalid number '{token}'"))?;
+        count += 1;
+    }
+    if count < required {
+        return Err(format!("expected {required} numbers, found {count}"));
+    }
+    Ok(out)
+}
+
+/// Parses a `v`, `v/vt`, `v//vn` or `v/vt/vn` face corner into zero-based indices.
+fn parse_corner(
+    token: &str,
+    positions: usize,
+    uvs: usize,
+    normals: usize,
+) -> Result<(u32, Option<u32>, Option<u32>), String> {
+    let mut parts = token.split('/');
+    let position = resolve_index(parts.next().unwrap_or(""), positions, "position")?;
+    let uv = match parts.next() {
+        None | Some("") => None,
+        Some(uv) => Some(resolve_index(uv, uvs, "texture coordinate")?),
+    };
+    let normal = match parts.next() {
+        None | Some("") => None,
+        Some(normal) => Some(resolve_index(normal, normals, "normal")?),
+    };
+    Ok((position, uv, normal))
+}
+
+/// OBJ indices are one-based, negative indices count back from the last element.
+fn resolve_index(token: &str, count: usize, what: &str) -> Result<u32, String> {
+    let index: i64 = token
+        .parse()
+        .map_err(|_| format!("invalid {what} index '{token}'"))?;
+    let resolved = if index < 0 {
+        count as i64 + index
+    } else {
+        index - 1
+    };
+    if resolved < 0 || resolved >= count as i64 {
+        return Err(format!("{what} index {index} is out of range"));
+    }
+    Ok(resolved as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Chart, ChartType, Mesh, MeshData, Vertex};
+    use std::borrow::Cow;
+
+    const QUAD: [[f32; 3]; 4] = [
+        [0.0, 0.0, 0.0],
+        [1.0, 0.0, 0.0],
+        [1.0, 1.0, 0.0],
+        [0.0, 1.0, 0.0],
+    ];
+    const NORMALS: [[f32; 3]; 4] = [[0.0, 0.0, 1.0]; 4];
+
+    fn parse(data: &str) -> Result<ObjMesh, ObjError> {
+        ObjMesh::parse(data.as_bytes())
+    }
+
+    #[test]
+    fn negative_indices_count_back_from_the_last_element() {
+        let mesh =
+            parse("v 0 0 0\nv 1 0 0\nv 1 1 0\nvt 0 0\nvt 1 0\nvt 1 1\nf -3/-3 -2/-2 -1/-1\n")
+                .unwrap();
+        assert_eq!(mesh.position_indices, [0, 1, 2]);
+        assert_eq!(mesh.uvs, [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0]]);
+        assert_eq!(mesh.indices, [0, 1, 2]);
+    }
+
+    #[test]
+    fn backslash_continues_a_line() {
+        let mesh = parse("v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 \\\n3 4\n").unwrap();
+        assert_eq!(mesh.face_vertex_counts, [4]);
+        assert_eq!(mesh.indices, [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn identical_corners_share_a_vertex() {
+        let mesh = parse(
+            "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvt 0 0\nvt 1 1\nvn 0 0 1\n\
+             f 1/1/1 2/1/1 3/2/1\nf 1/1/1 3/2/1 4/1/1\nf 1/2/1 3/2/1 4/1/1\n",
+        )
+        .unwrap();
+        // 1/2/1 differs from 1/1/1 in its UV, so it's the only new vertex of the last face.
+        assert_eq!(mesh.indices, [0, 1, 2, 0, 2, 3, 4, 2, 3]);
+        assert_eq!(mesh.position_indices, [0, 1, 2, 3, 0]);
+        assert_eq!(mesh.normals, [[0.0, 0.0, 1.0]; 5]);
+    }
+
+    #[test]
+    fn usemtl_assigns_material_ids_in_order_of_first_use() {
+        let mesh = parse(
+            "v 0 0 0\nv 1 0 0\nv 1 1 0\n\
+             f 1 2 3\nusemtl red\nf 1 2 3\nusemtl blue\nf 1 2 3\nusemtl red\nf 1 2 3\n",
+        )
+        .unwrap();
+        let names: Vec<_> = mesh.materials.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, ["", "red", "blue"]);
+        assert_eq!(mesh.face_materials, [0, 1, 2, 1]);
+        assert_eq!(mesh.mesh_decl().face_material_data, Some(&[0, 1, 2, 1][..]));
+    }
+
+    #[test]
+    fn mtllib_fills_in_used_materials() {
+        let mut mesh =
+            parse("mtllib a.mtl b.mtl\nv 0 0 0\nv 1 0 0\nv 1 1 0\nusemtl wood\nf 1 2 3\n").unwrap();
+        assert_eq!(mesh.material_libraries, ["a.mtl", "b.mtl"]);
+
+        mesh.parse_mtl(
+            "newmtl unused\nKd 0 0 1\nnewmtl wood\nKd 0.5 0.25 0\nmap_Kd -s 2 2 1 wood.png\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(
+            mesh.materials,
+            [ObjMaterial {
+                name: "wood".to_owned(),
+                diffuse: Some([0.5, 0.25, 0.0]),
+                diffuse_texture: Some("wood.png".to_owned()),
+            }]
+        );
+    }
+
+    #[test]
+    fn out_of_range_indices_are_rejected() {
+        for face in [
+            "f 1 2 4",
+            "f 0 1 2",
+            "f -4 1 2",
+            "f 1/2 2/1 3/1",
+            "f 1//2 2//1 3//1",
+        ] {
+            let data = format!("v 0 0 0\nv 1 0 0\nv 1 1 0\nvt 0 0\nvn 0 0 1\n{face}\n");
+            match parse(&data) {
+                Err(LoadError::Parse(message)) => {
+                    assert!(message.starts_with("line 6: "), "{}", message)
+                }
+                result => panic!("{} parsed as {:?}", face, result),
+            }
+        }
+    }
+
+    /// The quad split into two charts of one triangle each, on a 4x4 atlas.
+    fn quad_output() -> AtlasOutput {
+        let vertex = |chart_index, uv, xref| Vertex {
+            atlas_index: 0,
+            chart_index,
+            uv,
+            xref,
+        };
+        let chart = |face| Chart {
+            face_array: Cow::Owned(vec![face]),
+            atlas_index: 0,
+            type_: ChartType::Planar,
+            material: 0,
+        };
+        AtlasOutput {
+            width: 4,
+            height: 4,
+            atlas_count: 1,
+            chart_count: 2,
+            texels_per_unit: 2.0,
+            utilization: vec![0.5],
+            image: None,
+            meshes: vec![Mesh {
+                index_array: Cow::Owned(vec![0, 1, 2, 3, 4, 5]),
+                chart_array: vec![chart(0), chart(1)],
+                vertex_array: vec![
+                    vertex(0, [0.0, 0.0], 0),
+                    vertex(0, [2.0, 0.0], 1),
+                    vertex(0, [2.0, 2.0], 2),
+                    vertex(1, [2.0, 2.0], 0),
+                    vertex(1, [4.0, 2.0], 2),
+                    vertex(1, [2.0, 4.0], 3),
+                ],
+            }],
+        }
+    }
+
+    #[test]
+    fn write_groups_the_faces_of_every_chart() {
+        let input = MeshDecl {
+            vertex_normal_data: Some(MeshData::Vec3(&NORMALS)),
+            ..MeshDecl::new(&QUAD[..])
+        };
+        let options = ObjWriteOptions {
+            groups: ObjGroups::Chart,
+        };
+        let mut obj = Vec::new();
+        write(&mut obj, &[input], &quad_output(), &options).unwrap();
+        assert_eq!(
+            String::from_utf8(obj).unwrap(),
+            "o mesh_0\n\
+             v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 0 0\nv 1 1 0\nv 0 1 0\n\
+             vt 0 0\nvt 0.5 0\nvt 0.5 0.5\nvt 0.5 0.5\nvt 1 0.5\nvt 0.5 1\n\
+             vn 0 0 1\nvn 0 0 1\nvn 0 0 1\nvn 0 0 1\nvn 0 0 1\nvn 0 0 1\n\
+             g chart_0\nf 1/1/1 2/2/2 3/3/3\n\
+             g chart_1\nf 4/4/4 5/5/5 6/6/6\n"
+        );
+    }
+
+    #[test]
+    fn write_rejects_too_few_normals() {
+        let input = MeshDecl {
+            vertex_normal_data: Some(MeshData::Vec3(&NORMALS[..3])),
+            ..MeshDecl::new(&QUAD[..])
+        };
+        let mut obj = Vec::new();
+        let error = write(
+            &mut obj,
+            &[input],
+            &quad_output(),
+            &ObjWriteOptions::default(),
+        )
+        .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,99 @@
+//! Trampoline that lets `ChartOptions::with_parameterizer` closures be used as xatlas's
+//! `ParameterizeFunc`.
+//!
+//! xatlas doesn't pass any user data to the parameterize function, so the closure is kept in
+//! a global slot for the duration of `compute_charts` / `generate`. Atlases that use a
+//! parameterizer therefore compute their charts one at a time.
+
+use crate::{progress, raw_slice, Cancelled};
+use std::any::Any;
+use std::cell::Cell;
+use std::panic::{self, AssertUnwindSafe};
+use std::slice;
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+
+/// Parameterizes a single chart. Receives the chart's vertex positions, the texture coordinates to
+/// overwrite (initially an orthogonal projection) and the chart's triangle indices.
+pub type Parameterizer = Arc<dyn Fn(&[[f32; 3]], &mut [[f32; 2]], &[u32]) + Send + Sync>;
+
+static ACTIVE: RwLock<Option<Parameterizer>> = RwLock::new(None);
+static EXCLUSIVE: Mutex<()> = Mutex::new(());
+static PANIC: Mutex<Option<Box<dyn Any + Send>>> = Mutex::new(None);
+
+thread_local! {
+    /// Set while a parameterizer runs on this thread.
+    static RUNNING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Runs `f` with `parameterizer` installed. If the parameterizer panics, the remaining charts
+/// keep xatlas's projection and the panic is returned as `Cancelled::Panicked` once xatlas
+/// has returned, like a panic in the progress callback.
+///
+/// A parameterizer that calls into another atlas with a parameterizer would wait for itself to
+/// finish, so that call panics instead, which cancels the outer operation.
+pub(crate) fn scope(
+    parameterizer: Option<&Parameterizer>,
+    f: impl FnOnce(),
+) -> Result<(), Cancelled> {
+    let parameterizer = match parameterizer {
+        None => {
+            f();
+            return Ok(());
+        }
+        Some(p) => p,
+    };
+
+    assert!(
+        !RUNNING.with(Cell::get),
+        "a parameterizer can't compute charts with another parameterizer"
+    );
+    let _exclusive = EXCLUSIVE.lock().unwrap_or_else(PoisonError::into_inner);
+    *ACTIVE.write().unwrap_or_else(PoisonError::into_inner) = Some(parameterizer.clone());
+    f();
+    *ACTIVE.write().unwrap_or_else(PoisonError::into_inner) = None;
+
+    match PANIC.lock().unwrap_or_else(PoisonError::into_inner).take() {
+        None => Ok(()),
+        Some(payload) => Err(Cancelled::Panicked(progress::panic_message(payload))),
+    }
+}
+
+pub(crate) unsafe extern "C" fn parameterize(
+    positions: *const f32,
+    texcoords: *mut f32,
+    vertex_count: u32,
+    indices: *const u32,
+    index_count: u32,
+) {
+    let parameterizer = match &*ACTIVE.read().unwrap_or_else(PoisonError::into_inner) {
+        None => return,
+        Some(p) => p.clone(),
+    };
+    if texcoords.is_null() || vertex_count == 0 {
+        return;
+    }
+    if PANIC
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .is_some()
+    {
+        return;
+    }
+
+    let positions = raw_slice(positions as *const [f32; 3], vertex_count);
+    let texcoords = slice::from_raw_parts_mut(texcoords as *mut [f32; 2], vertex_count as usize);
+    let indices = raw_slice(indices, index_count);
+
+    // Caught for the same reason as in progress_callback, then kept until scope() returns it.
+    RUNNING.with(|running| running.set(true));
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        parameterizer(positions, texcoords, indices)
+    }));
+    RUNNING.with(|running| running.set(false));
+    if let Err(payload) = result {
+        PANIC
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get_or_insert(payload);
+    }
+}
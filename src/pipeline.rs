@@ -0,0 +1,305 @@
+//! Type-state wrappers around `Xatlas` that only expose the calls that are valid at each stage:
+//! meshes are added to an `AtlasBuilder`, charts are computed into a `ChartedAtlas` and
+//! packed into a `PackedAtlas`, which is the only stage the results can be read from.
+
+use crate::{
+    AddMeshError, AllocatorInUse, AtlasOutput, CancellationToken, Cancelled, ChartOptions,
+    DensityReport, MeshDecl, MeshSource, PackOptions, ProgressCategory, UvMeshDecl, Xatlas,
+};
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::time::Instant;
+
+/// Input marker for builders that take meshes through `add_mesh`.
+pub enum MeshInput {}
+
+/// Input marker for builders that take UV meshes through `add_uv_mesh`.
+/// xatlas doesn't allow mixing the two in a single atlas.
+pub enum UvMeshInput {}
+
+/// First stage: collects the input meshes.
+pub struct AtlasBuilder<I = MeshInput> {
+    atlas: Xatlas,
+    input: PhantomData<I>,
+}
+
+/// Second stage: charts have been computed but not packed yet.
+pub struct ChartedAtlas<I = MeshInput> {
+    atlas: Xatlas,
+    input: PhantomData<I>,
+}
+
+/// Final stage: charts have been packed and the results can be read through `Deref<Target = Xatlas>`.
+pub struct PackedAtlas<I = MeshInput> {
+    atlas: Xatlas,
+    input: PhantomData<I>,
+}
+
+/// A stage transition that was cancelled. Hands back the atlas in the last stage it completed.
+pub struct Interrupted<T> {
+    pub atlas: T,
+    pub reason: Cancelled,
+}
+
+impl AtlasBuilder<MeshInput> {
+    pub fn new() -> Self {
+        Self {
+            atlas: Xatlas::new(),
+            input: PhantomData,
+        }
+    }
+
+    pub fn add_mesh(&mut self, mesh_decl: &MeshDecl<'_>) -> Result<(), AddMeshError> {
+        self.atlas.add_mesh(mesh_decl)
+    }
+
+    pub fn add_mesh_with_mesh_count_hint(
+        &mut self,
+        mesh_decl: &MeshDecl<'_>,
+        mesh_count_hint: u32,
+    ) -> Result<(), AddMeshError> {
+        self.atlas
+            .add_mesh_with_mesh_count_hint(mesh_decl, mesh_count_hint)
+    }
+
+    pub fn add_mesh_source<S: MeshSource + ?Sized>(
+        &mut self,
+        source: &S,
+    ) -> Result<(), AddMeshError> {
+        self.atlas.add_mesh_source(source)
+    }
+
+    pub fn add_mesh_join(&mut self) {
+        self.atlas.add_mesh_join()
+    }
+}
+
+impl AtlasBuilder<UvMeshInput> {
+    pub fn for_uv_meshes() -> Self {
+        Self {
+            atlas: Xatlas::new(),
+            input: PhantomData,
+        }
+    }
+
+    pub fn add_uv_mesh(&mut self, decl: &UvMeshDecl<'_>) -> Result<(), AddMeshError> {
+        self.atlas.add_uv_mesh(decl)
+    }
+}
+
+impl<I> AtlasBuilder<I> {
+    fn from_atlas(atlas: Xatlas) -> Self {
+        AtlasBuilder {
+            atlas,
+            input: PhantomData,
+        }
+    }
+
+    pub fn set_progress_callback(
+        &mut self,
+        callback: impl FnMut(ProgressCategory, i32) -> bool + Send + 'static,
+    ) {
+        self.atlas.set_progress_callback(callback)
+    }
+
+    pub fn clear_progress_callback(&mut self) {
+        self.atlas.clear_progress_callback()
+    }
+
+    pub fn set_cancellation_token(&mut self, token: Option<CancellationToken>) {
+        self.atlas.set_cancellation_token(token)
+    }
+
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.atlas.set_deadline(deadline)
+    }
+
+    pub fn set_memory_limit(&mut self, limit: Option<usize>) -> Result<(), AllocatorInUse> {
+        self.atlas.set_memory_limit(limit)
+    }
+
+    pub fn mesh_count(&self) -> u32 {
+        self.atlas.mesh_count()
+    }
+
+    /// Call after all add_mesh calls.
+    pub fn compute_charts(
+        mut self,
+        options: &ChartOptions,
+    ) -> Result<ChartedAtlas<I>, Interrupted<Self>> {
+        match self.atlas.compute_charts(options) {
+            Ok(()) => Ok(ChartedAtlas::new(self.atlas)),
+            Err(reason) => Err(Interrupted {
+                atlas: self,
+                reason,
+            }),
+        }
+    }
+
+    /// Equivalent to calling compute_charts and pack_charts in sequence.
+    pub fn generate(
+        mut self,
+        chart_options: &ChartOptions,
+        pack_options: &PackOptions,
+    ) -> Result<PackedAtlas<I>, Interrupted<Self>> {
+        match self.atlas.generate(chart_options, pack_options) {
+            Ok(()) => Ok(PackedAtlas::new(self.atlas)),
+            Err(reason) => Err(Interrupted {
+                atlas: self,
+                reason,
+            }),
+        }
+    }
+}
+
+impl Default for AtlasBuilder<MeshInput> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<I> ChartedAtlas<I> {
+    fn new(atlas: Xatlas) -> Self {
+        ChartedAtlas {
+            atlas,
+            input: PhantomData,
+        }
+    }
+
+    /// Recomputes the charts with different options. xatlas has no charts left if this is
+    /// cancelled, so the atlas falls back to an `AtlasBuilder`.
+    pub fn compute_charts(
+        mut self,
+        options: &ChartOptions,
+    ) -> Result<Self, Interrupted<AtlasBuilder<I>>> {
+        match self.atlas.compute_charts(options) {
+            Ok(()) => Ok(self),
+            Err(reason) => Err(Interrupted {
+                atlas: AtlasBuilder::from_atlas(self.atlas),
+                reason,
+            }),
+        }
+    }
+
+    pub fn chart_count(&self) -> u32 {
+        self.atlas.chart_count()
+    }
+
+    pub fn pack_charts(
+        mut self,
+        pack_options: &PackOptions,
+    ) -> Result<PackedAtlas<I>, Interrupted<Self>> {
+        match self.atlas.pack_charts(pack_options) {
+            Ok(()) => Ok(PackedAtlas::new(self.atlas)),
+            Err(reason) => Err(Interrupted {
+                atlas: self,
+                reason,
+            }),
+        }
+    }
+}
+
+impl<I> PackedAtlas<I> {
+    fn new(atlas: Xatlas) -> Self {
+        PackedAtlas {
+            atlas,
+            input: PhantomData,
+        }
+    }
+
+    /// Re-packs the charts with different options. The previous results are discarded even if
+    /// packing is cancelled, so a cancelled re-pack falls back to a `ChartedAtlas`.
+    pub fn pack_charts(
+        mut self,
+        pack_options: &PackOptions,
+    ) -> Result<Self, Interrupted<ChartedAtlas<I>>> {
+        match self.atlas.pack_charts(pack_options) {
+            Ok(()) => Ok(self),
+            Err(reason) => Err(Interrupted {
+                atlas: ChartedAtlas::new(self.atlas),
+                reason,
+            }),
+        }
+    }
+
+    /// Re-packs without `PackOptions::max_chart_size` if chart densities are not uniform, see
+    /// `Xatlas::equalize_texel_density`.
+    pub fn equalize_texel_density(
+        mut self,
+        inputs: &[MeshDecl<'_>],
+        pack_options: &PackOptions,
+        tolerance: f32,
+    ) -> Result<(Self, DensityReport), Interrupted<ChartedAtlas<I>>> {
+        match self
+            .atlas
+            .equalize_texel_density(inputs, pack_options, tolerance)
+        {
+            Ok(report) => Ok((self, report)),
+            Err(reason) => Err(Interrupted {
+                atlas: ChartedAtlas::new(self.atlas),
+                reason,
+            }),
+        }
+    }
+
+    /// Regenerates charts and packing with different options. Like `AtlasBuilder::generate`,
+    /// a cancelled regeneration falls back to an `AtlasBuilder`, whether charting or packing
+    /// was cancelled.
+    pub fn generate(
+        mut self,
+        chart_options: &ChartOptions,
+        pack_options: &PackOptions,
+    ) -> Result<Self, Interrupted<AtlasBuilder<I>>> {
+        match self.atlas.generate(chart_options, pack_options) {
+            Ok(()) => Ok(self),
+            Err(reason) => Err(Interrupted {
+                atlas: AtlasBuilder::from_atlas(self.atlas),
+                reason,
+            }),
+        }
+    }
+
+    /// Recomputes the charts, which invalidates the current packing. xatlas has no charts left
+    /// if this is cancelled, so the atlas falls back to an `AtlasBuilder`.
+    pub fn compute_charts(
+        mut self,
+        options: &ChartOptions,
+    ) -> Result<ChartedAtlas<I>, Interrupted<AtlasBuilder<I>>> {
+        match self.atlas.compute_charts(options) {
+            Ok(()) => Ok(ChartedAtlas::new(self.atlas)),
+            Err(reason) => Err(Interrupted {
+                atlas: AtlasBuilder::from_atlas(self.atlas),
+                reason,
+            }),
+        }
+    }
+
+    pub fn into_output(self) -> AtlasOutput {
+        self.atlas.into_output()
+    }
+}
+
+impl<I> Deref for PackedAtlas<I> {
+    type Target = Xatlas;
+
+    fn deref(&self) -> &Xatlas {
+        &self.atlas
+    }
+}
+
+impl<T> fmt::Debug for Interrupted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Interrupted")
+            .field("reason", &self.reason)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> fmt::Display for Interrupted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.reason.fmt(f)
+    }
+}
+
+impl<T> std::error::Error for Interrupted<T> {}
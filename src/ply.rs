@@ -0,0 +1,512 @@
+//! Stanford PLY import, in the ASCII and both binary encodings.
+//!
+//! Vertices keep the order of the file, so `Vertex::xref` in the output refers to the vertex
+//! element directly.
+//!
+//! ```no_run
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let ply = xatlas_rs::ply::PlyMesh::load("scan.ply")?;
+//! let mut atlas = xatlas_rs::Xatlas::new();
+//! atlas.add_mesh(&ply.mesh_decl())?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{LoadError, MeshDecl};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+#[derive(Debug, Clone, Default)]
+pub struct PlyMesh {
+    pub positions: Vec<[f32; 3]>,
+    /// Empty if the file doesn't have normals.
+    pub normals: Vec<[f32; 3]>,
+    /// Empty if the file doesn't have texture coordinates.
+    pub uvs: Vec<[f32; 2]>,
+    pub indices: Vec<u32>,
+    /// Number of vertices of every face.
+    pub face_vertex_counts: Vec<u8>,
+}
+
+pub type PlyError = LoadError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+#[derive(Debug)]
+enum PropertyType {
+    Scalar(Scalar),
+    List { count: Scalar, item: Scalar },
+}
+
+#[derive(Debug)]
+struct Property {
+    name: String,
+    type_: PropertyType,
+}
+
+#[derive(Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+/// Reads the values of the properties in the file's encoding.
+struct ValueReader<R> {
+    reader: R,
+    format: Format,
+    /// Remaining tokens of the current ASCII line.
+    tokens: Vec<String>,
+}
+
+impl PlyMesh {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, PlyError> {
+        Self::parse(BufReader::new(File::open(path)?))
+    }
+
+    pub fn parse(mut reader: impl BufRead) -> Result<Self, PlyError> {
+        let (format, elements) = parse_header(&mut reader)?;
+        let mut values = ValueReader {
+            reader,
+            format,
+            tokens: Vec::new(),
+        };
+
+        let mut mesh = PlyMesh::default();
+        for element in &elements {
+            match element.name.as_str() {
+                "vertex" => mesh.read_vertices(element, &mut values)?,
+                "face" => mesh.read_faces(element, &mut values)?,
+                // Edges, materials and other elements are skipped.
+                _ => {
+                    for _ in 0..element.count {
+                        for property in &element.properties {
+                            values.skip(&property.type_)?;
+                        }
+                    }
+                }
+            }
+        }
+
+        let vertex_count = mesh.positions.len();
+        if let Some(&index) = mesh.indices.iter().find(|&&i| i as usize >= vertex_count) {
+            return Err(PlyError::Parse(format!(
+                "vertex index {index} is out of range for {vertex_count} vertices"
+            )));
+        }
+        Ok(mesh)
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn face_count(&self) -> usize {
+        self.face_vertex_counts.len()
+    }
+
+    /// Declaration for `Xatlas::add_mesh`. Face vertex counts are only passed when the mesh has
+    /// faces that aren't triangles.
+    pub fn mesh_decl(&self) -> MeshDecl<'_> {
+        MeshDecl::from_polygons(
+            &self.positions,
+            &self.normals,
+            &self.uvs,
+            &self.indices,
+            &self.face_vertex_counts,
+        )
+    }
+
+    fn read_vertices(
+        &mut self,
+        element: &Element,
+        values: &mut ValueReader<impl BufRead>,
+    ) -> Result<(), PlyError> {
+        let find = |names: &[&str]| {
+            element
+                .properties
+                .iter()
+                .position(|property| names.contains(&property.name.as_str()))
+        };
+        let position = [find(&["x"]), find(&["y"]), find(&["z"])];
+        let normal = [find(&["nx"]), find(&["ny"]), find(&["nz"])];
+        let uv = [
+            find(&["u", "s", "texture_u", "texture_s"]),
+            find(&["v", "t", "texture_v", "texture_t"]),
+        ];
+        if position.contains(&None) {
+            return Err(PlyError::Parse(
+                "vertex element is missing x, y or z".to_owned(),
+            ));
+        }
+        let has_normals = !normal.contains(&None);
+        let has_uvs = !uv.contains(&None);
+
+        let mut row = vec![0.0; element.properties.len()];
+        self.positions.reserve(element.count);
+        for _ in 0..element.count {
+            for (value, property) in row.iter_mut().zip(&element.properties) {
+                *value = match property.type_ {
+                    PropertyType::Scalar(scalar) => values.read(scalar)?,
+                    ref list => {
+                        values.skip(list)?;
+                        0.0
+                    }
+                };
+            }
+            let get = |index: Option<usize>| index.map_or(0.0, |i| row[i] as f32);
+            self.positions.push(position.map(get));
+            if has_normals {
+                self.normals.push(normal.map(get));
+            }
+            if has_uvs {
+                self.uvs.push(uv.map(get));
+            }
+        }
+        Ok(())
+    }
+
+    fn read_faces(
+        &mut self,
+        element: &Element,
+        values: &mut ValueReader<impl BufRead>,
+    ) -> Result<(), PlyError> {
+        let indices = element
+            .properties
+            .iter()
+            .position(|property| {
+                property.name == "vertex_indices" || property.name == "vertex_index"
+            })
+            .ok_or_else(|| PlyError::Parse("face element has no vertex_indices".to_owned()))?;
+        let (count_type, item_type) = match element.properties[indices].type_ {
+            PropertyType::List { count, item } => (count, item),
+            PropertyType::Scalar(_) => {
+                return Err(PlyError::Parse(format!(
+                    "face property {} is not a list",
+                    element.properties[indices].name
+                )))
+            }
+        };
+
+        for face in 0..element.count {
+            for (i, property) in element.properties.iter().enumerate() {
+                if i != indices {
+                    values.skip(&property.type_)?;
+                    continue;
+                }
+                let count = values.read(count_type)? as usize;
+                if !(3..=u8::MAX as usize).contains(&count) {
+                    return Err(PlyError::Parse(format!(
+                        "face {face} has {count} vertices, expected between 3 and {}",
+                        u8::MAX
+                    )));
+                }
+                for _ in 0..count {
+                    let index = values.read(item_type)?;
+                    if index < 0.0 {
+                        return Err(PlyError::Parse(format!(
+                            "face {face} has a negative vertex index"
+                        )));
+                    }
+                    self.indices.push(index as u32);
+                }
+                self.face_vertex_counts.push(count as u8);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_header(reader: &mut impl BufRead) -> Result<(Format, Vec<Element>), PlyError> {
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    let mut line = String::new();
+
+    for line_number in 1.. {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(PlyError::Parse("missing end_header".to_owned()));
+        }
+        let error =
+            |message: &str| PlyError::Parse(format!("header line {line_number}: {message}"));
+        let mut tokens = line.split_whitespace();
+        let keyword = tokens.next().unwrap_or("");
+        if line_number == 1 {
+            if keyword != "ply" {
+                return Err(error("not a PLY file"));
+            }
+            continue;
+        }
+
+        match keyword {
+            "format" => {
+                format = Some(match tokens.next() {
+                    Some("ascii") => Format::Ascii,
+                    Some("binary_little_endian") => Format::BinaryLittleEndian,
+                    Some("binary_big_endian") => Format::BinaryBigEndian,
+                    _ => return Err(error("unknown format")),
+                });
+            }
+            "element" => {
+                let name = tokens.next().ok_or_else(|| error("element without name"))?;
+                let count = tokens
+                    .next()
+                    .and_then(|count| count.parse().ok())
+                    .ok_or_else(|| error("invalid element count"))?;
+                elements.push(Element {
+                    name: name.to_owned(),
+                    count,
+                    properties: Vec::new(),
+                });
+            }
+            "property" => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| error("property before the first element"))?;
+                let scalar = |tokens: &mut std::str::SplitWhitespace<'_>| {
+                    tokens
+                        .next()
+                        .and_then(Scalar::parse)
+                        .ok_or_else(|| error("unknown property type"))
+                };
+                let type_ = if line.split_whitespace().nth(1) == Some("list") {
+                    tokens.next();
+                    PropertyType::List {
+                        count: scalar(&mut tokens)?,
+                        item: scalar(&mut tokens)?,
+                    }
+                } else {
+                    PropertyType::Scalar(scalar(&mut tokens)?)
+                };
+                let name = tokens
+                    .next()
+                    .ok_or_else(|| error("property without name"))?;
+                element.properties.push(Property {
+                    name: name.to_owned(),
+                    type_,
+                });
+            }
+            "end_header" => break,
+            // comment, obj_info
+            _ => {}
+        }
+    }
+
+    let format = format.ok_or_else(|| PlyError::Parse("header has no format".to_owned()))?;
+    Ok((format, elements))
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+}
+
+impl<R: BufRead> ValueReader<R> {
+    fn read(&mut self, scalar: Scalar) -> Result<f64, PlyError> {
+        if self.format == Format::Ascii {
+            let token = self.next_token()?;
+            return token
+                .parse()
+                .map_err(|_| PlyError::Parse(format!("invalid number '{token}'")));
+        }
+
+        let mut bytes = [0; 8];
+        let bytes = &mut bytes[..scalar.size()];
+        self.reader.read_exact(bytes).map_err(|e| {
+            if e.kind() == io::ErrorKind::UnexpectedEof {
+                PlyError::Parse("unexpected end of file".to_owned())
+            } else {
+                PlyError::Io(e)
+            }
+        })?;
+        if self.format == Format::BinaryBigEndian {
+            bytes.reverse();
+        }
+        Ok(match scalar {
+            Scalar::I8 => bytes[0] as i8 as f64,
+            Scalar::U8 => bytes[0] as f64,
+            Scalar::I16 => i16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+            Scalar::U16 => u16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+            Scalar::I32 => i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+            Scalar::U32 => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+            Scalar::F32 => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+            Scalar::F64 => f64::from_le_bytes([
+                bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
+            ]),
+        })
+    }
+
+    fn skip(&mut self, type_: &PropertyType) -> Result<(), PlyError> {
+        match *type_ {
+            PropertyType::Scalar(scalar) => {
+                self.read(scalar)?;
+            }
+            PropertyType::List { count, item } => {
+                for _ in 0..self.read(count)? as usize {
+                    self.read(item)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn next_token(&mut self) -> Result<String, PlyError> {
+        while self.tokens.is_empty() {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Err(PlyError::Parse("unexpected end of file".to_owned()));
+            }
+            self.tokens = line.split_whitespace().rev().map(str::to_owned).collect();
+        }
+        Ok(self.tokens.pop().expect("tokens aren't empty"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "element vertex 4
+property float x
+property float y
+property float z
+property float s
+property float t
+element face 2
+property uchar intensity
+property list uchar int vertex_indices
+end_header
+";
+
+    const POSITIONS: [[f32; 3]; 4] = [
+        [0.0, 0.0, 0.0],
+        [1.0, 0.0, 0.0],
+        [1.0, 1.0, 0.0],
+        [0.0, 1.0, 0.5],
+    ];
+
+    /// A quad followed by a triangle, in the binary encoding given by `to_bytes`.
+    fn binary(format: &str, to_bytes: fn(&[u8]) -> Vec<u8>) -> Vec<u8> {
+        let mut data = format!("ply\nformat {format} 1.0\n{HEADER}").into_bytes();
+        for (i, position) in POSITIONS.iter().enumerate() {
+            for value in position.iter().chain(&[i as f32, 0.5]) {
+                data.extend(to_bytes(&value.to_le_bytes()));
+            }
+        }
+        for face in [&[0i32, 1, 2, 3][..], &[0, 2, 1]] {
+            data.extend([7, face.len() as u8]);
+            for &index in face {
+                data.extend(to_bytes(&index.to_le_bytes()));
+            }
+        }
+        data
+    }
+
+    fn check(mesh: &PlyMesh) {
+        assert_eq!(mesh.positions, POSITIONS);
+        assert_eq!(mesh.uvs, [[0.0, 0.5], [1.0, 0.5], [2.0, 0.5], [3.0, 0.5]]);
+        assert!(mesh.normals.is_empty());
+        assert_eq!(mesh.indices, [0, 1, 2, 3, 0, 2, 1]);
+        assert_eq!(mesh.face_vertex_counts, [4, 3]);
+
+        let decl = mesh.mesh_decl();
+        assert_eq!(decl.face_count, 2);
+        assert_eq!(decl.face_vertex_count, Some(&[4, 3][..]));
+        assert_eq!(decl.validate(), Ok(()));
+    }
+
+    #[test]
+    fn parses_ascii() {
+        let data = format!(
+            "ply\nformat ascii 1.0\ncomment a quad and a triangle\n{HEADER}\
+             0 0 0 0 0.5\n1 0 0 1 0.5\n1 1 0 2 0.5\n0 1 0.5 3 0.5\n\
+             7 4 0 1 2 3\n7 3 0 2 1\n"
+        );
+        check(&PlyMesh::parse(data.as_bytes()).unwrap());
+    }
+
+    #[test]
+    fn parses_binary_little_endian() {
+        let data = binary("binary_little_endian", |bytes| bytes.to_vec());
+        check(&PlyMesh::parse(&data[..]).unwrap());
+    }
+
+    #[test]
+    fn parses_binary_big_endian() {
+        let data = binary("binary_big_endian", |bytes| {
+            bytes.iter().rev().copied().collect()
+        });
+        check(&PlyMesh::parse(&data[..]).unwrap());
+    }
+
+    #[test]
+    fn rejects_truncated_and_out_of_range_data() {
+        let mut data = binary("binary_little_endian", |bytes| bytes.to_vec());
+        data.truncate(data.len() - 2);
+        assert!(matches!(PlyMesh::parse(&data[..]), Err(PlyError::Parse(_))));
+
+        let data = format!(
+            "ply\nformat ascii 1.0\n{HEADER}\
+             0 0 0 0 0\n1 0 0 0 0\n1 1 0 0 0\n0 1 0 0 0\n7 3 0 1 4\n7 3 0 2 1\n"
+        );
+        assert!(matches!(
+            PlyMesh::parse(data.as_bytes()),
+            Err(PlyError::Parse(_))
+        ));
+    }
+    #[test]
+    fn rejects_scalar_vertex_indices() {
+        let header = HEADER.replace(
+            "property list uchar int vertex_indices",
+            "property int vertex_indices",
+        );
+        let data = format!(
+            "ply\nformat ascii 1.0\n{header}\
+             0 0 0 0 0\n1 0 0 0 0\n1 1 0 0 0\n0 1 0 0 0\n7 0\n7 1\n"
+        );
+        match PlyMesh::parse(data.as_bytes()) {
+            Err(PlyError::Parse(message)) => {
+                assert_eq!(message, "face property vertex_indices is not a list")
+            }
+            result => panic!("parsed scalar vertex_indices: {:?}", result.map(|_| ())),
+        }
+    }
+}
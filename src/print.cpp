@@ -0,0 +1,25 @@
+// Formats xatlas's printf style messages and hands them to the Rust side of the crate,
+// which forwards them to the `log` or `tracing` crates.
+#include <cstdarg>
+#include <cstdio>
+#include <vector>
+
+extern "C" void xatlas_rs_log_message(const char *message);
+
+extern "C" int xatlas_rs_print(const char *format, ...) {
+	char buffer[512];
+	va_list args, argsCopy;
+	va_start(args, format);
+	va_copy(argsCopy, args);
+	const int length = vsnprintf(buffer, sizeof(buffer), format, args);
+	va_end(args);
+	if (length >= 0 && (size_t)length < sizeof(buffer)) {
+		xatlas_rs_log_message(buffer);
+	} else if (length >= 0) {
+		std::vector<char> large((size_t)length + 1);
+		vsnprintf(large.data(), large.size(), format, argsCopy);
+		xatlas_rs_log_message(large.data());
+	}
+	va_end(argsCopy);
+	return length;
+}
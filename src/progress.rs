@@ -0,0 +1,224 @@
+//! Progress callback trampoline and the cancellation state it records.
+
+use crate::root::xatlas;
+use crate::{memory, ProgressCategory};
+use std::any::Any;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Instant;
+
+pub(crate) type ProgressCallback = Box<dyn FnMut(ProgressCategory, i32) -> bool + Send>;
+
+/// Lets another thread cancel `compute_charts`, `pack_charts` or `generate`.
+/// Clones share the same flag. xatlas only checks it when it reports progress.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Clears the flag so the token can be used for the next operation.
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::Relaxed)
+    }
+}
+
+/// Why `compute_charts`, `pack_charts` or `generate` stopped before finishing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Cancelled {
+    /// The progress callback returned false.
+    Callback,
+    /// The atlas's `CancellationToken` was cancelled.
+    Token,
+    /// The atlas's deadline passed.
+    Deadline,
+    /// The atlas allocated more memory than its memory limit.
+    MemoryLimit,
+    /// The progress callback or the parameterizer panicked, with the panic message if it had
+    /// one.
+    Panicked(String),
+}
+
+/// State shared with the progress trampoline through xatlas's user data pointer.
+/// Boxed by `Xatlas` so its address stays stable.
+#[derive(Default)]
+pub(crate) struct Progress {
+    pub(crate) callback: Option<ProgressCallback>,
+    pub(crate) token: Option<CancellationToken>,
+    pub(crate) deadline: Option<Instant>,
+    /// Only set while the atlas has a memory limit.
+    pub(crate) memory: Option<Arc<memory::Usage>>,
+    pub(crate) cancelled: Option<Cancelled>,
+}
+
+impl Progress {
+    /// Whether xatlas needs to call the trampoline at all.
+    pub(crate) fn is_active(&self) -> bool {
+        self.callback.is_some()
+            || self.token.is_some()
+            || self.deadline.is_some()
+            || self.memory.is_some()
+    }
+
+    /// Checks the conditions that don't depend on the callback.
+    pub(crate) fn check(&self) -> Result<(), Cancelled> {
+        if self
+            .token
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled)
+        {
+            return Err(Cancelled::Token);
+        }
+        if self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            return Err(Cancelled::Deadline);
+        }
+        if self
+            .memory
+            .as_deref()
+            .is_some_and(memory::Usage::is_exceeded)
+        {
+            return Err(Cancelled::MemoryLimit);
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn lock(progress: &Mutex<Progress>) -> std::sync::MutexGuard<'_, Progress> {
+    progress.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+pub(crate) unsafe extern "C" fn progress_callback(
+    category: xatlas::ProgressCategory,
+    progress: std::os::raw::c_int,
+    user_data: *mut std::os::raw::c_void,
+) -> bool {
+    let progress: i32 = progress;
+    let category = match category {
+        xatlas::ProgressCategory_AddMesh => ProgressCategory::AddMesh,
+        xatlas::ProgressCategory_ComputeCharts => ProgressCategory::ComputeCharts,
+        xatlas::ProgressCategory_PackCharts => ProgressCategory::PackCharts,
+        xatlas::ProgressCategory_BuildOutputMeshes => ProgressCategory::BuildOutputMeshes,
+        _ => unreachable!(),
+    };
+
+    // xatlas may report progress from its worker threads, the lock serializes the callback.
+    let mut guard = lock(&*(user_data as *const Mutex<Progress>));
+    let state = &mut *guard;
+    if state.cancelled.is_some() {
+        return false;
+    }
+    if let Err(cancelled) = state.check() {
+        state.cancelled = Some(cancelled);
+        return false;
+    }
+
+    let callback = match &mut state.callback {
+        None => return true,
+        Some(callback) => callback,
+    };
+
+    // Unwinding into C++ would abort, so turn the panic into a cancellation instead.
+    match panic::catch_unwind(AssertUnwindSafe(|| callback(category, progress))) {
+        Ok(true) => true,
+        Ok(false) => {
+            state.cancelled = Some(Cancelled::Callback);
+            false
+        }
+        Err(payload) => {
+            state.cancelled = Some(Cancelled::Panicked(panic_message(payload)));
+            false
+        }
+    }
+}
+
+/// The message of a panic, empty if the payload isn't a string.
+pub(crate) fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => payload
+            .downcast_ref::<&str>()
+            .map_or_else(String::new, |message| (*message).to_owned()),
+    }
+}
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Cancelled::Callback => f.write_str("cancelled by the progress callback"),
+            Cancelled::Token => f.write_str("cancelled through the cancellation token"),
+            Cancelled::Deadline => f.write_str("deadline passed"),
+            Cancelled::MemoryLimit => f.write_str("memory limit exceeded"),
+            Cancelled::Panicked(message) if message.is_empty() => f.write_str("callback panicked"),
+            Cancelled::Panicked(message) => write!(f, "callback panicked: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AtlasBuilder, Interrupted, Xatlas};
+
+    fn report(progress: &Mutex<Progress>) -> bool {
+        let user_data = progress as *const Mutex<Progress> as *mut _;
+        unsafe { progress_callback(xatlas::ProgressCategory_ComputeCharts, 50, user_data) }
+    }
+
+    #[test]
+    fn clearing_the_callback_stops_calling_it() {
+        let mut atlas = Xatlas::new();
+        atlas.set_progress_callback(|_, _| false);
+        assert!(!report(&atlas.progress));
+        assert_eq!(
+            lock(&atlas.progress).cancelled.take(),
+            Some(Cancelled::Callback)
+        );
+
+        atlas.clear_progress_callback();
+        assert!(!lock(&atlas.progress).is_active());
+        assert!(report(&atlas.progress));
+        assert_eq!(lock(&atlas.progress).cancelled, None);
+    }
+
+    #[test]
+    fn a_panicking_callback_cancels_with_its_message() {
+        let progress = Mutex::new(Progress {
+            callback: Some(Box::new(|_, progress| panic!("stopped at {}", progress))),
+            ..Progress::default()
+        });
+        assert!(!report(&progress));
+        let cancelled = lock(&progress).cancelled.take();
+        assert_eq!(
+            cancelled,
+            Some(Cancelled::Panicked("stopped at 50".to_owned()))
+        );
+        assert_eq!(
+            cancelled.unwrap().to_string(),
+            "callback panicked: stopped at 50"
+        );
+    }
+
+    #[test]
+    fn cancellations_are_sync() {
+        fn assert_sync<T: Send + Sync>() {}
+        assert_sync::<Cancelled>();
+        assert_sync::<Interrupted<AtlasBuilder>>();
+    }
+}
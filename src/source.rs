@@ -0,0 +1,288 @@
+//! Meshes that are read vertex by vertex and face by face, for geometry that isn't stored in
+//! slices. `Xatlas::add_mesh_source` gathers them into the buffers xatlas reads.
+
+use crate::{AddMeshError, IndexData, MeshData, MeshDecl};
+use std::convert::TryFrom;
+
+/// Geometry that xatlas can read through `Xatlas::add_mesh_source`.
+pub trait MeshSource {
+    fn vertex_count(&self) -> u32;
+
+    fn face_count(&self) -> u32;
+
+    fn position(&self, vertex: u32) -> [f32; 3];
+
+    /// Normals are only passed to xatlas if every vertex has one.
+    fn normal(&self, _vertex: u32) -> Option<[f32; 3]> {
+        None
+    }
+
+    /// Input UVs, a hint for the chart generator. Only passed to xatlas if every vertex has one.
+    fn uv(&self, _vertex: u32) -> Option<[f32; 2]> {
+        None
+    }
+
+    /// Appends the vertex indices of `face` to `indices`. Faces with more than 3 vertices are
+    /// polygons, which xatlas triangulates. Filling a buffer instead of returning a slice lets
+    /// sources compute faces on the fly.
+    fn face(&self, face: u32, indices: &mut Vec<u32>);
+
+    /// Appends the vertex indices of every face to `indices`, in order, and the number of
+    /// vertices of every face to `counts`. Reads each face through `face` by default; sources
+    /// that can only locate a face by walking the faces before it should read them in one pass.
+    fn faces(&self, indices: &mut Vec<u32>, counts: &mut Vec<usize>) {
+        for face in 0..self.face_count() {
+            let start = indices.len();
+            self.face(face, indices);
+            counts.push(indices.len() - start);
+        }
+    }
+
+    /// Only faces with the same material are assigned to the same chart. Materials are only
+    /// passed to xatlas if every face has one.
+    fn material(&self, _face: u32) -> Option<u32> {
+        None
+    }
+
+    /// Ignored faces are not atlased, see `MeshDecl::face_ignore_data`.
+    fn is_ignored(&self, _face: u32) -> bool {
+        false
+    }
+
+    /// See `MeshDecl::epsilon`.
+    fn epsilon(&self) -> f32 {
+        MeshDecl::default().epsilon
+    }
+
+    /// Checks the source before `Xatlas::add_mesh_source` reads it. Sources whose accessors
+    /// panic on inconsistent data return the error here instead.
+    fn validate(&self) -> Result<(), AddMeshError> {
+        Ok(())
+    }
+}
+
+impl<T: MeshSource + ?Sized> MeshSource for &T {
+    fn vertex_count(&self) -> u32 {
+        (**self).vertex_count()
+    }
+
+    fn face_count(&self) -> u32 {
+        (**self).face_count()
+    }
+
+    fn position(&self, vertex: u32) -> [f32; 3] {
+        (**self).position(vertex)
+    }
+
+    fn normal(&self, vertex: u32) -> Option<[f32; 3]> {
+        (**self).normal(vertex)
+    }
+
+    fn uv(&self, vertex: u32) -> Option<[f32; 2]> {
+        (**self).uv(vertex)
+    }
+
+    fn face(&self, face: u32, indices: &mut Vec<u32>) {
+        (**self).face(face, indices)
+    }
+
+    fn faces(&self, indices: &mut Vec<u32>, counts: &mut Vec<usize>) {
+        (**self).faces(indices, counts)
+    }
+
+    fn material(&self, face: u32) -> Option<u32> {
+        (**self).material(face)
+    }
+
+    fn is_ignored(&self, face: u32) -> bool {
+        (**self).is_ignored(face)
+    }
+
+    fn epsilon(&self) -> f32 {
+        (**self).epsilon()
+    }
+
+    fn validate(&self) -> Result<(), AddMeshError> {
+        (**self).validate()
+    }
+}
+
+/// Reads the declaration's streams. For polygon meshes, `face` has to add up the vertex counts
+/// of all previous faces; `faces` reads all of them in a single pass.
+impl MeshSource for MeshDecl<'_> {
+    fn vertex_count(&self) -> u32 {
+        self.vertex_position_data.count(3)
+    }
+
+    fn face_count(&self) -> u32 {
+        self.resolved_face_count()
+    }
+
+    fn position(&self, vertex: u32) -> [f32; 3] {
+        self.vertex_position_data.read(vertex, 3)
+    }
+
+    fn normal(&self, vertex: u32) -> Option<[f32; 3]> {
+        Some(self.vertex_normal_data.as_ref()?.read(vertex, 3))
+    }
+
+    fn uv(&self, vertex: u32) -> Option<[f32; 2]> {
+        let uv = self.vertex_uv_data.as_ref()?.read(vertex, 2);
+        Some([uv[0], uv[1]])
+    }
+
+    fn face(&self, face: u32, indices: &mut Vec<u32>) {
+        let (start, count) = match self.face_vertex_count {
+            Some(counts) => {
+                let start: u32 = counts[..face as usize].iter().map(|&c| c as u32).sum();
+                (start, counts[face as usize] as u32)
+            }
+            None => (face * 3, 3),
+        };
+        indices.extend(
+            (start..start + count).map(|position| match &self.index_data {
+                Some(index_data) => index_data.get(position),
+                None => position,
+            }),
+        );
+    }
+
+    fn faces(&self, indices: &mut Vec<u32>, counts: &mut Vec<usize>) {
+        let face_count = self.face_count() as usize;
+        let corners = match self.face_vertex_count {
+            Some(face_vertex_count) => {
+                counts.extend(face_vertex_count[..face_count].iter().map(|&c| c as usize));
+                face_vertex_count[..face_count]
+                    .iter()
+                    .map(|&c| c as u32)
+                    .sum()
+            }
+            None => {
+                counts.extend(std::iter::repeat_n(3, face_count));
+                face_count as u32 * 3
+            }
+        };
+        indices.extend((0..corners).map(|position| match &self.index_data {
+            Some(index_data) => index_data.get(position),
+            None => position,
+        }));
+    }
+
+    fn material(&self, face: u32) -> Option<u32> {
+        Some(self.face_material_data?[face as usize])
+    }
+
+    fn is_ignored(&self, face: u32) -> bool {
+        self.face_ignore_data
+            .is_some_and(|ignored| ignored[face as usize])
+    }
+
+    fn epsilon(&self) -> f32 {
+        self.epsilon
+    }
+
+    /// The stream accessors index the slices directly, so they panic on invalid declarations.
+    fn validate(&self) -> Result<(), AddMeshError> {
+        MeshDecl::validate(self)
+    }
+}
+
+/// The streams of a `MeshSource`, laid out as a `MeshDecl` needs them.
+pub(crate) struct Gathered {
+    positions: Vec<[f32; 3]>,
+    normals: Option<Vec<[f32; 3]>>,
+    uvs: Option<Vec<[f32; 2]>>,
+    indices: Vec<u32>,
+    /// `None` if every face is a triangle.
+    face_vertex_count: Option<Vec<u8>>,
+    materials: Option<Vec<u32>>,
+    ignored: Option<Vec<bool>>,
+    epsilon: f32,
+}
+
+impl Gathered {
+    pub(crate) fn new(source: &(impl MeshSource + ?Sized)) -> Result<Self, AddMeshError> {
+        source.validate()?;
+        let vertices = 0..source.vertex_count();
+        let faces = 0..source.face_count();
+
+        let mut indices = Vec::with_capacity(faces.len() * 3);
+        let mut counts = Vec::with_capacity(faces.len());
+        source.faces(&mut indices, &mut counts);
+        let face_vertex_count = counts
+            .iter()
+            .enumerate()
+            .map(|(face, &count)| {
+                u8::try_from(count).map_err(|_| AddMeshError::TooManyFaceVertices {
+                    face: face as u32,
+                    count,
+                })
+            })
+            .collect::<Result<Vec<u8>, _>>()?;
+        let is_triangles = face_vertex_count.iter().all(|&count| count == 3);
+        let ignored: Vec<bool> = faces.clone().map(|face| source.is_ignored(face)).collect();
+
+        Ok(Gathered {
+            positions: vertices.clone().map(|v| source.position(v)).collect(),
+            normals: vertices.clone().map(|v| source.normal(v)).collect(),
+            uvs: vertices.map(|v| source.uv(v)).collect(),
+            indices,
+            face_vertex_count: (!is_triangles).then_some(face_vertex_count),
+            materials: faces.map(|face| source.material(face)).collect(),
+            ignored: ignored.contains(&true).then_some(ignored),
+            epsilon: source.epsilon(),
+        })
+    }
+
+    pub(crate) fn mesh_decl(&self) -> MeshDecl<'_> {
+        MeshDecl {
+            vertex_position_data: MeshData::Vec3(&self.positions),
+            vertex_normal_data: self.normals.as_deref().map(MeshData::Vec3),
+            vertex_uv_data: self.uvs.as_deref().map(MeshData::Vec2),
+            index_data: Some(IndexData::U32(&self.indices)),
+            face_ignore_data: self.ignored.as_deref(),
+            face_material_data: self.materials.as_deref(),
+            face_vertex_count: self.face_vertex_count.as_deref(),
+            face_count: self
+                .face_vertex_count
+                .as_ref()
+                .map_or(0, |counts| counts.len() as u32),
+            epsilon: self.epsilon,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gathering_a_decl_keeps_its_faces_and_epsilon() {
+        let positions = [
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [1.0, 1.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [2.0, 0.0, 0.0],
+        ];
+        let indices = [0u32, 1, 2, 3, 1, 4, 2];
+        let decl = MeshDecl {
+            face_vertex_count: Some(&[4, 3]),
+            epsilon: 0.01,
+            ..MeshDecl::new(&positions[..]).with_indices(&indices[..])
+        };
+
+        let mut one_by_one = Vec::new();
+        for face in 0..decl.face_count() {
+            decl.face(face, &mut one_by_one);
+        }
+        let gathered = Gathered::new(&decl).unwrap();
+        assert_eq!(gathered.indices, one_by_one);
+        assert_eq!(gathered.indices, indices);
+
+        let gathered_decl = gathered.mesh_decl();
+        assert_eq!(gathered_decl.face_vertex_count, Some(&[4, 3][..]));
+        assert_eq!(gathered_decl.epsilon, 0.01);
+        assert_eq!(gathered_decl.validate(), Ok(()));
+    }
+}
@@ -0,0 +1,277 @@
+//! STL import, ASCII and binary.
+//!
+//! STL stores three separate vertices for every triangle, so xatlas would see every triangle as
+//! its own island. Vertices with identical positions are welded while loading; `original_vertices`
+//! maps the welded vertices, and through them `Vertex::xref`, back to the file's vertex order.
+//!
+//! ```no_run
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let stl = xatlas_rs::stl::StlMesh::load("part.stl")?;
+//! let mut atlas = xatlas_rs::Xatlas::new();
+//! atlas.add_mesh(&stl.mesh_decl())?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{IndexData, LoadError, MeshData, MeshDecl};
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+#[derive(Debug, Clone, Default)]
+pub struct StlMesh {
+    /// Welded positions, in order of their first occurrence in the file.
+    pub positions: Vec<[f32; 3]>,
+    /// Three welded vertices per triangle. Since the file has three vertices per triangle,
+    /// this also maps every vertex of the file, `triangle * 3 + corner`, to its welded vertex.
+    pub indices: Vec<u32>,
+    /// For every welded vertex, the first vertex of the file it was welded from.
+    pub original_vertices: Vec<u32>,
+    /// The normal stored with every triangle.
+    pub facet_normals: Vec<[f32; 3]>,
+}
+
+pub type StlError = LoadError;
+
+/// Welds vertices with bitwise identical positions, treating -0.0 and 0.0 as equal.
+#[derive(Default)]
+struct Welder {
+    vertices: HashMap<[u32; 3], u32>,
+}
+
+impl StlMesh {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, StlError> {
+        Self::from_slice(&fs::read(path)?)
+    }
+
+    pub fn parse(mut reader: impl Read) -> Result<Self, StlError> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        Self::from_slice(&data)
+    }
+
+    /// Parses ASCII or binary STL. Binary files may also start with `solid`, so the encoding is
+    /// decided by whether the triangle count in the binary header matches the data size.
+    pub fn from_slice(data: &[u8]) -> Result<Self, StlError> {
+        let binary_size = data
+            .get(80..84)
+            .map(|count| 84 + 50 * u32::from_le_bytes(count.try_into().unwrap()) as usize);
+        if binary_size == Some(data.len()) || !data.trim_ascii_start().starts_with(b"solid") {
+            Self::parse_binary(data)
+        } else {
+            Self::parse_ascii(&String::from_utf8_lossy(data))
+        }
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn face_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    /// Declaration for `Xatlas::add_mesh`. Facet normals aren't passed since they don't belong
+    /// to the welded vertices.
+    pub fn mesh_decl(&self) -> MeshDecl<'_> {
+        MeshDecl {
+            vertex_position_data: MeshData::Vec3(&self.positions),
+            index_data: Some(IndexData::U32(&self.indices)),
+            face_count: self.face_count() as u32,
+            ..MeshDecl::default()
+        }
+    }
+
+    fn parse_binary(data: &[u8]) -> Result<Self, StlError> {
+        let count = data
+            .get(80..84)
+            .map(|count| u32::from_le_bytes(count.try_into().unwrap()) as usize)
+            .ok_or_else(|| StlError::Parse("file is shorter than the binary header".to_owned()))?;
+        let triangles = &data[84..];
+        if triangles.len() < count * 50 {
+            return Err(StlError::Parse(format!(
+                "header declares {count} triangles, the file only has room for {}",
+                triangles.len() / 50
+            )));
+        }
+
+        let mut mesh = StlMesh::default();
+        let mut welder = Welder::default();
+        let read = |bytes: &[u8]| {
+            let mut vector = [0.0; 3];
+            for (value, bytes) in vector.iter_mut().zip(bytes.chunks_exact(4)) {
+                *value = f32::from_le_bytes(bytes.try_into().unwrap());
+            }
+            vector
+        };
+        // Every triangle is a normal, three vertices and a 2 byte attribute.
+        for triangle in triangles.chunks_exact(50).take(count) {
+            mesh.facet_normals.push(read(&triangle[0..12]));
+            for corner in triangle[12..48].chunks_exact(12) {
+                welder.add(&mut mesh, read(corner));
+            }
+        }
+        Ok(mesh)
+    }
+
+    fn parse_ascii(text: &str) -> Result<Self, StlError> {
+        let mut mesh = StlMesh::default();
+        let mut welder = Welder::default();
+        let mut facet_vertices = 0;
+
+        for (line_index, line) in text.lines().enumerate() {
+            let error =
+                |message: String| StlError::Parse(format!("line {}: {message}", line_index + 1));
+            let mut tokens = line.split_whitespace();
+            match tokens.next() {
+                Some("facet") => {
+                    if tokens.next() != Some("normal") {
+                        return Err(error("expected 'facet normal'".to_owned()));
+                    }
+                    mesh.facet_normals
+                        .push(parse_vector(tokens).map_err(error)?);
+                    facet_vertices = 0;
+                }
+                Some("vertex") => {
+                    if facet_vertices == 3 {
+                        return Err(error("facet has more than 3 vertices".to_owned()));
+                    }
+                    welder.add(&mut mesh, parse_vector(tokens).map_err(error)?);
+                    facet_vertices += 1;
+                }
+                Some("endfacet") if facet_vertices != 3 => {
+                    return Err(error(format!("facet has {facet_vertices} vertices")));
+                }
+                // solid, outer loop, endloop, endsolid
+                _ => {}
+            }
+        }
+        if mesh.indices.len() != mesh.facet_normals.len() * 3 {
+            return Err(StlError::Parse("last facet is incomplete".to_owned()));
+        }
+        Ok(mesh)
+    }
+}
+
+impl Welder {
+    fn add(&mut self, mesh: &mut StlMesh, position: [f32; 3]) {
+        let original = mesh.indices.len() as u32;
+        let key = position.map(|v| if v == 0.0 { 0 } else { v.to_bits() });
+        let index = *self.vertices.entry(key).or_insert_with(|| {
+            mesh.positions.push(position);
+            mesh.original_vertices.push(original);
+            mesh.positions.len() as u32 - 1
+        });
+        mesh.indices.push(index);
+    }
+}
+
+fn parse_vector<'a>(tokens: impl Iterator<Item = &'a str>) -> Result<[f32; 3], String> {
+    let mut vector = [0.0; 3];
+    let mut count = 0;
+    for (value, token) in vector.iter_mut().zip(tokens) {
+        *value = token
+            .parse()
+            .map_err(|_| format!("invalid number '{token}'"))?;
+        count += 1;
+    }
+    if count < 3 {
+        return Err(format!("expected 3 numbers, found {count}"));
+    }
+    Ok(vector)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ASCII: &str = "solid quad
+facet normal 0 0 1
+  outer loop
+    vertex 0 0 0
+    vertex 1 0 0
+    vertex 1 1 0
+  endloop
+endfacet
+facet normal 0 0 1
+  outer loop
+    vertex 0 0 0
+    vertex 1 1 0
+    vertex -0 1 0
+  endloop
+endfacet
+endsolid quad
+";
+
+    /// The triangles of `ASCII` in the binary encoding, after the given 80 byte header.
+    fn binary(header: &[u8]) -> Vec<u8> {
+        let triangles: [[[f32; 3]; 4]; 2] = [
+            [
+                [0.0, 0.0, 1.0],
+                [0.0, 0.0, 0.0],
+                [1.0, 0.0, 0.0],
+                [1.0, 1.0, 0.0],
+            ],
+            [
+                [0.0, 0.0, 1.0],
+                [0.0, 0.0, 0.0],
+                [1.0, 1.0, 0.0],
+                [-0.0, 1.0, 0.0],
+            ],
+        ];
+        let mut data = header.to_vec();
+        data.resize(80, b' ');
+        data.extend(2u32.to_le_bytes());
+        for triangle in &triangles {
+            for value in triangle.iter().flatten() {
+                data.extend(value.to_le_bytes());
+            }
+            data.extend([0, 0]);
+        }
+        data
+    }
+
+    fn check(mesh: &StlMesh) {
+        assert_eq!(mesh.vertex_count(), 4);
+        assert_eq!(mesh.face_count(), 2);
+        assert_eq!(mesh.indices, [0, 1, 2, 0, 2, 3]);
+        assert_eq!(mesh.original_vertices, [0, 1, 2, 5]);
+        assert_eq!(mesh.facet_normals, [[0.0, 0.0, 1.0]; 2]);
+        assert_eq!(mesh.mesh_decl().validate(), Ok(()));
+    }
+
+    #[test]
+    fn parses_ascii_and_welds_vertices() {
+        let mesh = StlMesh::from_slice(ASCII.as_bytes()).unwrap();
+        check(&mesh);
+        assert_eq!(mesh.positions[3], [-0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn parses_binary() {
+        check(&StlMesh::from_slice(&binary(b"exported part")).unwrap());
+    }
+
+    #[test]
+    fn detects_binary_files_whose_header_starts_with_solid() {
+        check(&StlMesh::from_slice(&binary(b"solid part exported as binary")).unwrap());
+    }
+
+    #[test]
+    fn rejects_incomplete_facets() {
+        let data = ASCII.replace("    vertex -0 1 0\n", "");
+        assert!(matches!(
+            StlMesh::from_slice(data.as_bytes()),
+            Err(StlError::Parse(_))
+        ));
+
+        let mut data = binary(b"");
+        data.truncate(data.len() - 50);
+        assert!(matches!(
+            StlMesh::from_slice(&data),
+            Err(StlError::Parse(_))
+        ));
+    }
+}
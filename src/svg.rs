@@ -0,0 +1,234 @@
+//! SVG rendering of the UV layout of an atlas page, for reviewing unwraps in tools that diff text.
+
+use crate::image::chart_color;
+use crate::{ChartType, Mesh};
+use std::fmt::Write;
+
+#[derive(Debug, Clone)]
+pub struct SvgOptions {
+    /// `PackOptions::padding` the atlas was packed with. Charts are surrounded by a gutter this
+    /// many texels wide. 0 doesn't draw gutters.
+    pub padding: u32,
+    /// Label every chart with its index and type.
+    pub labels: bool,
+}
+
+impl Default for SvgOptions {
+    fn default() -> Self {
+        SvgOptions {
+            padding: 0,
+            labels: true,
+        }
+    }
+}
+
+/// Draws the triangles of every chart on atlas `atlas_index` in texel coordinates. Charts are
+/// filled with a color per chart and outlined with a color per `ChartType`.
+pub(crate) fn render(
+    meshes: &[Mesh<'_>],
+    width: u32,
+    height: u32,
+    atlas_index: u32,
+    options: &SvgOptions,
+) -> String {
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}">"#
+    );
+    let _ = writeln!(
+        svg,
+        r##"<rect x="0" y="0" width="{width}" height="{height}" fill="#202020" stroke="#ffffff" stroke-width="1"/>"##
+    );
+
+    let mut labels = String::new();
+    for mesh in meshes {
+        for chart in &mesh.chart_array {
+            if chart.atlas_index != atlas_index || chart.face_array.is_empty() {
+                continue;
+            }
+            let triangles: Vec<[[f32; 2]; 3]> = chart
+                .face_array
+                .iter()
+                .map(|&face| {
+                    let corner = |i: usize| {
+                        let index = mesh.index_array[face as usize * 3 + i];
+                        mesh.vertex_array[index as usize].uv
+                    };
+                    [corner(0), corner(1), corner(2)]
+                })
+                .collect();
+            let first_vertex = mesh.index_array[chart.face_array[0] as usize * 3];
+            let chart_index = mesh.vertex_array[first_vertex as usize].chart_index.max(0) as u32;
+            let [r, g, b] = chart_color(chart_index);
+
+            let _ = writeln!(
+                svg,
+                r#"<g id="chart-{chart_index}" class="{}">"#,
+                type_name(chart.type_)
+            );
+            if options.padding > 0 {
+                // Strokes twice as wide as the padding extend it on both sides of the edges,
+                // so together they cover the gutter around the chart.
+                let _ = writeln!(
+                    svg,
+                    r##"<g fill="none" stroke="#606060" stroke-width="{}" stroke-linejoin="round" opacity="0.5">"##,
+                    options.padding * 2
+                );
+                for triangle in &triangles {
+                    let _ = writeln!(svg, r#"<polygon points="{}"/>"#, points(triangle));
+                }
+                svg.push_str("</g>\n");
+            }
+            let _ = writeln!(
+                svg,
+                r#"<g fill="rgb({r},{g},{b})" fill-opacity="0.6" stroke="{}" stroke-width="0.5">"#,
+                type_color(chart.type_)
+            );
+            for triangle in &triangles {
+                let _ = writeln!(svg, r#"<polygon points="{}"/>"#, points(triangle));
+            }
+            svg.push_str("</g>\n</g>\n");
+
+            if options.labels {
+                let corners = triangles.len() as f32 * 3.0;
+                let center = triangles.iter().flatten().fold([0.0; 2], |sum, uv| {
+                    [sum[0] + uv[0] / corners, sum[1] + uv[1] / corners]
+                });
+                let _ = writeln!(
+                    labels,
+                    r#"<text x="{}" y="{}">{chart_index} {}</text>"#,
+                    number(center[0]),
+                    number(center[1]),
+                    type_name(chart.type_)
+                );
+            }
+        }
+    }
+
+    if !labels.is_empty() {
+        svg.push_str(
+            r##"<g font-family="monospace" font-size="8" fill="#ffffff" text-anchor="middle" dominant-baseline="middle">"##,
+        );
+        svg.push('\n');
+        svg.push_str(&labels);
+        svg.push_str("</g>\n");
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn points(triangle: &[[f32; 2]; 3]) -> String {
+    triangle
+        .iter()
+        .map(|uv| format!("{},{}", number(uv[0]), number(uv[1])))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Rounds to 1/1000 of a texel and drops trailing zeros, so the output stays stable and short.
+fn number(value: f32) -> String {
+    let text = format!("{value:.3}");
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    if text == "-0" {
+        "0".to_owned()
+    } else {
+        text.to_owned()
+    }
+}
+
+fn type_name(type_: ChartType) -> &'static str {
+    match type_ {
+        ChartType::Planar => "planar",
+        ChartType::Ortho => "ortho",
+        ChartType::LSCM => "lscm",
+        ChartType::Piecewise => "piecewise",
+        ChartType::Invalid => "invalid",
+    }
+}
+
+fn type_color(type_: ChartType) -> &'static str {
+    match type_ {
+        ChartType::Planar => "#4080ff",
+        ChartType::Ortho => "#40c040",
+        ChartType::LSCM => "#ff9020",
+        ChartType::Piecewise => "#c040ff",
+        ChartType::Invalid => "#ff2020",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Chart, Vertex};
+    use std::borrow::Cow;
+
+    /// A chart per entry of `charts`, each a list of triangles, on the atlas in `atlas_index`.
+    fn mesh(charts: &[(u32, &[[[f32; 2]; 3]])]) -> Mesh<'static> {
+        let mut mesh = Mesh {
+            index_array: Cow::Owned(Vec::new()),
+            chart_array: Vec::new(),
+            vertex_array: Vec::new(),
+        };
+        for (chart_index, &(atlas_index, triangles)) in charts.iter().enumerate() {
+            let mut faces = Vec::new();
+            for triangle in triangles {
+                faces.push(mesh.index_array.len() as u32 / 3);
+                for &uv in triangle {
+                    mesh.index_array
+                        .to_mut()
+                        .push(mesh.vertex_array.len() as u32);
+                    mesh.vertex_array.push(Vertex {
+                        atlas_index: atlas_index as i32,
+                        chart_index: chart_index as i32,
+                        uv,
+                        xref: 0,
+                    });
+                }
+            }
+            mesh.chart_array.push(Chart {
+                face_array: Cow::Owned(faces),
+                atlas_index,
+                type_: ChartType::Planar,
+                material: 0,
+            });
+        }
+        mesh
+    }
+
+    #[test]
+    fn renders_the_triangles_of_every_chart_on_the_page() {
+        let mesh = mesh(&[
+            (
+                0,
+                &[
+                    [[1.0, 1.0], [5.0, 1.0], [5.0, 5.0]],
+                    [[1.0, 1.0], [5.0, 5.0], [1.0, 5.0]],
+                ],
+            ),
+            (0, &[[[8.0, 1.0], [12.5, 1.0], [8.0, 6.25]]]),
+            (1, &[[[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]]]),
+        ]);
+        let options = SvgOptions {
+            padding: 0,
+            labels: false,
+        };
+        let svg = render(&[mesh], 16, 8, 0, &options);
+
+        assert!(svg.starts_with("<svg "));
+        assert!(svg.contains(r#"width="16" height="8" viewBox="0 0 16 8""#));
+        assert_eq!(svg.matches("<polygon ").count(), 3);
+        assert!(svg.contains(r#"<polygon points="8,1 12.5,1 8,6.25"/>"#));
+        assert!(!svg.contains("<text"));
+
+        let fills: Vec<&str> = svg
+            .match_indices(r#"fill="rgb("#)
+            .map(|(start, _)| svg[start..].split('"').nth(1).unwrap())
+            .collect();
+        assert_eq!(fills.len(), 2);
+        assert_ne!(fills[0], fills[1]);
+        assert!(svg.contains(r#"<g id="chart-0" class="planar">"#));
+        assert!(svg.contains(r#"<g id="chart-1" class="planar">"#));
+        assert!(!svg.contains(r#"id="chart-2""#));
+    }
+}
@@ -0,0 +1,649 @@
+//! Checks of a packed layout: overlapping charts, folded triangles and charts outside the atlas.
+
+use crate::metrics::flipped_faces;
+use crate::{AtlasOutput, Mesh};
+use std::collections::BTreeMap;
+
+/// Overlaps smaller than this, in texels, are treated as touching.
+const EPSILON: f32 = 1e-4;
+
+/// A face of an output mesh, indexing `Mesh::index_array` in triangles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FaceRef {
+    pub mesh: u32,
+    pub face: u32,
+}
+
+/// A chart of an output mesh, indexing `Mesh::chart_array`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ChartRef {
+    pub mesh: u32,
+    pub chart: u32,
+}
+
+/// Two triangles of different charts cover the same area of an atlas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TriangleOverlap {
+    pub atlas_index: u32,
+    pub first: FaceRef,
+    pub second: FaceRef,
+}
+
+/// Two charts are closer than the padding allows, or share texels that bilinear filtering reads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TexelOverlap {
+    pub atlas_index: u32,
+    pub first: ChartRef,
+    pub second: ChartRef,
+    /// Texels of `first` that are within the padding of `second`.
+    pub texel_count: u32,
+}
+
+/// A chart whose UVs leave the atlas.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChartOutOfBounds {
+    pub chart: ChartRef,
+    pub atlas_index: u32,
+    pub min: [f32; 2],
+    pub max: [f32; 2],
+}
+
+/// Result of `AtlasOutput::validate`. Every list is sorted, so reports can be compared in tests.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LayoutReport {
+    pub triangle_overlaps: Vec<TriangleOverlap>,
+    pub texel_overlaps: Vec<TexelOverlap>,
+    /// Triangles whose UV winding differs from the majority of their chart.
+    pub flipped_triangles: Vec<FaceRef>,
+    pub out_of_bounds: Vec<ChartOutOfBounds>,
+}
+
+impl LayoutReport {
+    pub fn is_valid(&self) -> bool {
+        self.triangle_overlaps.is_empty()
+            && self.texel_overlaps.is_empty()
+            && self.flipped_triangles.is_empty()
+            && self.out_of_bounds.is_empty()
+    }
+}
+
+/// A chart's triangle in atlas space.
+struct Triangle {
+    chart: ChartRef,
+    face: FaceRef,
+    uv: [[f32; 2]; 3],
+    min: [f32; 2],
+    max: [f32; 2],
+}
+
+pub(crate) fn validate(output: &AtlasOutput, resolution: u32, padding: u32) -> LayoutReport {
+    let (width, height) = if resolution == 0 {
+        (output.width, output.height)
+    } else {
+        (resolution, resolution)
+    };
+
+    let mut report = LayoutReport::default();
+    let mut pages: Vec<Vec<Triangle>> = (0..output.atlas_count).map(|_| Vec::new()).collect();
+    for (mesh_index, mesh) in output.meshes.iter().enumerate() {
+        for (chart_index, chart) in mesh.chart_array.iter().enumerate() {
+            let chart_ref = ChartRef {
+                mesh: mesh_index as u32,
+                chart: chart_index as u32,
+            };
+            let triangles: Vec<Triangle> = chart
+                .face_array
+                .iter()
+                .map(|&face| triangle(mesh, chart_ref, face))
+                .collect();
+
+            report
+                .flipped_triangles
+                .extend(flipped_faces(mesh, chart).map(|face| FaceRef {
+                    mesh: mesh_index as u32,
+                    face,
+                }));
+
+            if let Some(bounds) = bounds(&triangles) {
+                let (min, max) = bounds;
+                if min[0] < -EPSILON
+                    || min[1] < -EPSILON
+                    || max[0] > width as f32 + EPSILON
+                    || max[1] > height as f32 + EPSILON
+                    || chart.atlas_index >= output.atlas_count
+                {
+                    report.out_of_bounds.push(ChartOutOfBounds {
+                        chart: chart_ref,
+                        atlas_index: chart.atlas_index,
+                        min,
+                        max,
+                    });
+                }
+            }
+
+            if let Some(page) = pages.get_mut(chart.atlas_index as usize) {
+                page.extend(triangles);
+            }
+        }
+    }
+
+    for (atlas_index, page) in pages.iter_mut().enumerate() {
+        let atlas_index = atlas_index as u32;
+        sweep_overlaps(page, atlas_index, &mut report.triangle_overlaps);
+        texel_overlaps(
+            page,
+            atlas_index,
+            width,
+            height,
+            padding,
+            &mut report.texel_overlaps,
+        );
+    }
+
+    report
+        .triangle_overlaps
+        .sort_by_key(|o| (o.atlas_index, o.first, o.second));
+    report
+        .texel_overlaps
+        .sort_by_key(|o| (o.atlas_index, o.first, o.second));
+    report.flipped_triangles.sort();
+    report
+}
+
+fn triangle(mesh: &Mesh<'_>, chart: ChartRef, face: u32) -> Triangle {
+    let uv = [0, 1, 2].map(|i| {
+        let index = mesh.index_array[face as usize * 3 + i];
+        mesh.vertex_array[index as usize].uv
+    });
+    let min = [0, 1].map(|axis| uv.iter().map(|p| p[axis]).fold(f32::INFINITY, f32::min));
+    let max = [0, 1].map(|axis| uv.iter().map(|p| p[axis]).fold(f32::NEG_INFINITY, f32::max));
+    Triangle {
+        chart,
+        face: FaceRef {
+            mesh: chart.mesh,
+            face,
+        },
+        uv,
+        min,
+        max,
+    }
+}
+
+fn bounds(triangles: &[Triangle]) -> Option<([f32; 2], [f32; 2])> {
+    let first = triangles.first()?;
+    Some(
+        triangles
+            .iter()
+            .fold((first.min, first.max), |(min, max), t| {
+                (
+                    [min[0].min(t.min[0]), min[1].min(t.min[1])],
+                    [max[0].max(t.max[0]), max[1].max(t.max[1])],
+                )
+            }),
+    )
+}
+
+/// Sweeps along x, only testing triangles of different charts whose bounding boxes overlap.
+fn sweep_overlaps(page: &mut [Triangle], atlas_index: u32, overlaps: &mut Vec<TriangleOverlap>) {
+    page.sort_by(|a, b| a.min[0].total_cmp(&b.min[0]));
+    let mut active: Vec<usize> = Vec::new();
+    for i in 0..page.len() {
+        let current = &page[i];
+        active.retain(|&j| page[j].max[0] > current.min[0] + EPSILON);
+        for &j in &active {
+            let other = &page[j];
+            if other.chart == current.chart
+                || other.max[1] <= current.min[1] + EPSILON
+                || current.max[1] <= other.min[1] + EPSILON
+            {
+                continue;
+            }
+            if triangles_overlap(&current.uv, &other.uv) {
+                let (first, second) = if current.face < other.face {
+                    (current.face, other.face)
+                } else {
+                    (other.face, current.face)
+                };
+                overlaps.push(TriangleOverlap {
+                    atlas_index,
+                    first,
+                    second,
+                });
+            }
+        }
+        active.push(i);
+    }
+}
+
+/// Rasterizes the charts of a page and reports charts whose texels are within `padding` texels
+/// of each other. A texel belongs to a chart if the area bilinear filtering samples it from,
+/// the texel grown by half a texel on every side, touches one of the chart's triangles.
+fn texel_overlaps(
+    page: &[Triangle],
+    atlas_index: u32,
+    width: u32,
+    height: u32,
+    padding: u32,
+    overlaps: &mut Vec<TexelOverlap>,
+) {
+    let (width, height) = (width as usize, height as usize);
+    if width == 0 || height == 0 {
+        return;
+    }
+    let mut charts = BTreeMap::<ChartRef, Vec<&Triangle>>::new();
+    for triangle in page {
+        charts.entry(triangle.chart).or_default().push(triangle);
+    }
+    let masks: Vec<TexelMask> = charts
+        .iter()
+        .map(|(&chart, triangles)| TexelMask::rasterize(chart, triangles, width, height))
+        .collect();
+
+    let mut owners: Vec<Option<ChartRef>> = vec![None; width * height];
+    let mut pairs = BTreeMap::<(ChartRef, ChartRef), u32>::new();
+    for mask in &masks {
+        for (x, y) in mask.texels() {
+            match owners[y * width + x] {
+                Some(owner) => *pairs.entry((owner, mask.chart)).or_insert(0) += 1,
+                None => owners[y * width + x] = Some(mask.chart),
+            }
+        }
+    }
+
+    // Masks are in chart order, so a texel's owner is the smallest chart covering it. Texels of
+    // that chart within the padding of a later chart are counted for the pair.
+    if padding > 0 {
+        for mask in &masks {
+            for (x, y) in mask.dilate(padding as usize, width, height).texels() {
+                match owners[y * width + x] {
+                    Some(owner) if owner < mask.chart => {
+                        *pairs.entry((owner, mask.chart)).or_insert(0) += 1
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    overlaps.extend(
+        pairs
+            .into_iter()
+            .map(|((first, second), texel_count)| TexelOverlap {
+                atlas_index,
+                first,
+                second,
+                texel_count,
+            }),
+    );
+}
+
+/// The texels of a chart, within a rectangle of the page.
+struct TexelMask {
+    chart: ChartRef,
+    x0: usize,
+    y0: usize,
+    width: usize,
+    height: usize,
+    covered: Vec<bool>,
+}
+
+impl TexelMask {
+    fn rasterize(chart: ChartRef, triangles: &[&Triangle], width: usize, height: usize) -> Self {
+        let (min, max) = triangles.iter().fold(
+            ([f32::INFINITY; 2], [f32::NEG_INFINITY; 2]),
+            |(min, max), t| {
+                (
+                    [min[0].min(t.min[0]), min[1].min(t.min[1])],
+                    [max[0].max(t.max[0]), max[1].max(t.max[1])],
+                )
+            },
+        );
+        let x0 = (min[0] - 1.5).floor().max(0.0) as usize;
+        let y0 = (min[1] - 1.5).floor().max(0.0) as usize;
+        let x1 = ((max[0] + 0.5).ceil().max(0.0) as usize).min(width - 1);
+        let y1 = ((max[1] + 0.5).ceil().max(0.0) as usize).min(height - 1);
+        let mut mask = TexelMask {
+            chart,
+            x0,
+            y0,
+            width: (x1 + 1).saturating_sub(x0),
+            height: (y1 + 1).saturating_sub(y0),
+            covered: Vec::new(),
+        };
+        mask.covered = vec![false; mask.width * mask.height];
+
+        for triangle in triangles {
+            let tx0 = ((triangle.min[0] - 1.5).floor().max(0.0) as usize).max(x0);
+            let ty0 = ((triangle.min[1] - 1.5).floor().max(0.0) as usize).max(y0);
+            let tx1 = ((triangle.max[0] + 0.5).ceil().max(0.0) as usize).min(x1);
+            let ty1 = ((triangle.max[1] + 0.5).ceil().max(0.0) as usize).min(y1);
+            for y in ty0..=ty1 {
+                for x in tx0..=tx1 {
+                    let square = [
+                        [x as f32 - 0.5, y as f32 - 0.5],
+                        [x as f32 + 1.5, y as f32 + 1.5],
+                    ];
+                    if triangle_overlaps_square(&triangle.uv, square) {
+                        mask.covered[(y - y0) * mask.width + (x - x0)] = true;
+                    }
+                }
+            }
+        }
+        mask
+    }
+
+    /// Page coordinates of the covered texels.
+    fn texels(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.covered
+            .iter()
+            .enumerate()
+            .filter(|(_, &covered)| covered)
+            .map(move |(i, _)| (self.x0 + i % self.width, self.y0 + i / self.width))
+    }
+
+    /// Grows the mask by `padding` texels in every direction, clipped to the page. Rows and then
+    /// columns are dilated separately, so the cost doesn't depend on the padding.
+    fn dilate(&self, padding: usize, width: usize, height: usize) -> Self {
+        if self.covered.is_empty() {
+            return TexelMask {
+                covered: Vec::new(),
+                ..*self
+            };
+        }
+        let x0 = self.x0.saturating_sub(padding);
+        let y0 = self.y0.saturating_sub(padding);
+        let grown_width = (self.x0 + self.width + padding).min(width) - x0;
+        let grown_height = (self.y0 + self.height + padding).min(height) - y0;
+
+        let mut rows = vec![false; grown_width * self.height];
+        for y in 0..self.height {
+            let row = &self.covered[y * self.width..(y + 1) * self.width];
+            dilate_line(
+                row.iter().copied(),
+                self.x0 - x0,
+                padding,
+                &mut rows[y * grown_width..(y + 1) * grown_width],
+            );
+        }
+        let mut covered = vec![false; grown_width * grown_height];
+        let mut column = vec![false; grown_height];
+        for x in 0..grown_width {
+            dilate_line(
+                (0..self.height).map(|y| rows[y * grown_width + x]),
+                self.y0 - y0,
+                padding,
+                &mut column,
+            );
+            for (y, &value) in column.iter().enumerate() {
+                covered[y * grown_width + x] = value;
+            }
+        }
+
+        TexelMask {
+            chart: self.chart,
+            x0,
+            y0,
+            width: grown_width,
+            height: grown_height,
+            covered,
+        }
+    }
+}
+
+/// Sets every element of `out` that is within `padding` of a set element of `line`, which
+/// starts at `start` in `out`.
+fn dilate_line(line: impl Iterator<Item = bool>, start: usize, padding: usize, out: &mut [bool]) {
+    // Number of set elements before every position of `line`.
+    let mut prefix = vec![0u32];
+    for value in line {
+        prefix.push(prefix[prefix.len() - 1] + value as u32);
+    }
+    let len = prefix.len() - 1;
+    for (i, out) in out.iter_mut().enumerate() {
+        // Range of `line` within `padding` of `i`, in `line` coordinates.
+        let low = i.saturating_sub(start + padding);
+        let high = (i + padding + 1).saturating_sub(start).min(len);
+        *out = low < high && prefix[high] > prefix[low];
+    }
+}
+
+/// Separating axis test that ignores overlaps thinner than `EPSILON`, so triangles that only
+/// share an edge or a corner don't overlap.
+fn triangles_overlap(a: &[[f32; 2]; 3], b: &[[f32; 2]; 3]) -> bool {
+    edge_normals(a)
+        .iter()
+        .chain(edge_normals(b).iter())
+        .all(|&axis| projections_overlap(a, b, axis))
+}
+
+fn triangle_overlaps_square(triangle: &[[f32; 2]; 3], square: [[f32; 2]; 2]) -> bool {
+    let [min, max] = square;
+    let corners = [min, [max[0], min[1]], max, [min[0], max[1]]];
+    let normals = edge_normals(triangle);
+    [[1.0, 0.0], [0.0, 1.0], normals[0], normals[1], normals[2]]
+        .iter()
+        .all(|&axis| projections_overlap(triangle, &corners, axis))
+}
+
+fn edge_normals(triangle: &[[f32; 2]; 3]) -> [[f32; 2]; 3] {
+    [0, 1, 2].map(|i| {
+        let (a, b) = (triangle[i], triangle[(i + 1) % 3]);
+        let normal = [a[1] - b[1], b[0] - a[0]];
+        let length = (normal[0] * normal[0] + normal[1] * normal[1]).sqrt();
+        if length > 0.0 {
+            [normal[0] / length, normal[1] / length]
+        } else {
+            [0.0, 0.0]
+        }
+    })
+}
+
+fn projections_overlap(a: &[[f32; 2]], b: &[[f32; 2]], axis: [f32; 2]) -> bool {
+    if axis == [0.0, 0.0] {
+        return true;
+    }
+    let project = |points: &[[f32; 2]]| {
+        points
+            .iter()
+            .map(|p| p[0] * axis[0] + p[1] * axis[1])
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), d| {
+                (min.min(d), max.max(d))
+            })
+    };
+    let (a_min, a_max) = project(a);
+    let (b_min, b_max) = project(b);
+    a_max.min(b_max) - a_min.max(b_min) > EPSILON
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Chart, ChartType, Vertex};
+    use std::borrow::Cow;
+
+    type Uv = [[f32; 2]; 3];
+
+    /// A 16x16 atlas with one mesh, where every chart is a list of separate triangles.
+    fn output(charts: &[&[Uv]]) -> AtlasOutput {
+        let mut mesh = Mesh {
+            index_array: Cow::Owned(Vec::new()),
+            chart_array: Vec::new(),
+            vertex_array: Vec::new(),
+        };
+        for (chart_index, triangles) in charts.iter().enumerate() {
+            let mut faces = Vec::new();
+            for triangle in triangles.iter() {
+                faces.push(mesh.index_array.len() as u32 / 3);
+                for &uv in triangle {
+                    mesh.index_array
+                        .to_mut()
+                        .push(mesh.vertex_array.len() as u32);
+                    mesh.vertex_array.push(Vertex {
+                        atlas_index: 0,
+                        chart_index: chart_index as i32,
+                        uv,
+                        xref: 0,
+                    });
+                }
+            }
+            mesh.chart_array.push(Chart {
+                face_array: Cow::Owned(faces),
+                atlas_index: 0,
+                type_: ChartType::Planar,
+                material: 0,
+            });
+        }
+        AtlasOutput {
+            width: 16,
+            height: 16,
+            atlas_count: 1,
+            chart_count: charts.len() as u32,
+            texels_per_unit: 1.0,
+            utilization: vec![0.0],
+            image: None,
+            meshes: vec![mesh],
+        }
+    }
+
+    /// Two counter-clockwise triangles covering the rectangle from `min` to `max`.
+    fn rectangle(min: [f32; 2], max: [f32; 2]) -> [Uv; 2] {
+        [[min, [max[0], min[1]], max], [min, max, [min[0], max[1]]]]
+    }
+
+    fn chart(chart: u32) -> ChartRef {
+        ChartRef { mesh: 0, chart }
+    }
+
+    fn face(face: u32) -> FaceRef {
+        FaceRef { mesh: 0, face }
+    }
+
+    #[test]
+    fn reports_overlapping_triangles_of_different_charts() {
+        let first = [[1.0, 1.0], [6.0, 1.0], [1.0, 6.0]];
+        let second = [[3.0, 3.0], [8.0, 3.0], [3.0, 8.0]];
+        let report = output(&[&[first], &[second]]).validate(0, 0);
+        assert_eq!(
+            report.triangle_overlaps,
+            [TriangleOverlap {
+                atlas_index: 0,
+                first: face(0),
+                second: face(1),
+            }]
+        );
+        assert_eq!(report.texel_overlaps.len(), 1);
+        assert!(!report.is_valid());
+
+        // Triangles of the same chart are allowed to overlap.
+        let report = output(&[&[first, second]]).validate(0, 0);
+        assert!(report.triangle_overlaps.is_empty());
+    }
+
+    #[test]
+    fn triangles_sharing_an_edge_dont_overlap() {
+        let [lower, upper] = rectangle([2.0, 2.0], [10.0, 10.0]);
+        let report = output(&[&[lower], &[upper]]).validate(0, 0);
+        assert!(report.triangle_overlaps.is_empty());
+        // Bilinear filtering still reads texels of both charts along the diagonal.
+        assert_eq!(report.texel_overlaps.len(), 1);
+    }
+
+    #[test]
+    fn separated_charts_are_valid() {
+        let report = output(&[
+            &rectangle([1.0, 1.0], [3.0, 3.0]),
+            &rectangle([8.0, 1.0], [10.0, 3.0]),
+        ])
+        .validate(0, 0);
+        assert_eq!(report, LayoutReport::default());
+        assert!(report.is_valid());
+    }
+
+    #[test]
+    fn reports_charts_within_the_padding() {
+        // The charts cover texel columns 0 to 3 and 7 to 10.
+        let layout = output(&[
+            &rectangle([1.0, 1.0], [3.0, 3.0]),
+            &rectangle([8.0, 1.0], [10.0, 3.0]),
+        ]);
+        assert!(layout.validate(0, 3).texel_overlaps.is_empty());
+        assert_eq!(
+            layout.validate(0, 4).texel_overlaps,
+            [TexelOverlap {
+                atlas_index: 0,
+                first: chart(0),
+                second: chart(1),
+                // Column 3, rows 0 to 3.
+                texel_count: 4,
+            }]
+        );
+        assert_eq!(layout.validate(0, 5).texel_overlaps[0].texel_count, 8);
+    }
+
+    #[test]
+    fn dilation_matches_a_window_scan() {
+        let triangles = [
+            [[3.0, 2.0], [7.0, 4.0], [4.0, 6.0]],
+            [[9.0, 9.0], [11.0, 9.5], [9.5, 12.0]],
+        ];
+        let triangles: Vec<Triangle> = triangles
+            .iter()
+            .map(|&uv| {
+                let min = [0, 1].map(|a| uv.iter().map(|p| p[a]).fold(f32::INFINITY, f32::min));
+                let max = [0, 1].map(|a| uv.iter().map(|p| p[a]).fold(f32::NEG_INFINITY, f32::max));
+                Triangle {
+                    chart: chart(0),
+                    face: face(0),
+                    uv,
+                    min,
+                    max,
+                }
+            })
+            .collect();
+        let triangles: Vec<&Triangle> = triangles.iter().collect();
+        let mask = TexelMask::rasterize(chart(0), &triangles, 16, 16);
+        let covered: Vec<(usize, usize)> = mask.texels().collect();
+
+        for padding in [1, 2, 5] {
+            let mut expected: Vec<(usize, usize)> = (0..16usize)
+                .flat_map(|y| (0..16usize).map(move |x| (x, y)))
+                .filter(|&(x, y)| {
+                    covered
+                        .iter()
+                        .any(|&(cx, cy)| x.abs_diff(cx) <= padding && y.abs_diff(cy) <= padding)
+                })
+                .collect();
+            let mut dilated: Vec<(usize, usize)> = mask.dilate(padding, 16, 16).texels().collect();
+            expected.sort();
+            dilated.sort();
+            assert_eq!(dilated, expected, "padding {padding}");
+        }
+    }
+
+    #[test]
+    fn reports_flipped_triangles() {
+        let [lower, upper] = rectangle([1.0, 1.0], [5.0, 5.0]);
+        let flipped = [[6.0, 1.0], [6.0, 3.0], [8.0, 1.0]];
+        let report = output(&[&[lower, upper, flipped]]).validate(0, 0);
+        assert_eq!(report.flipped_triangles, [face(2)]);
+    }
+
+    #[test]
+    fn reports_charts_outside_the_atlas() {
+        let layout = output(&[
+            &rectangle([1.0, 1.0], [3.0, 3.0]),
+            &rectangle([12.0, 12.0], [17.0, 14.0]),
+        ]);
+        let report = layout.validate(0, 0);
+        assert_eq!(
+            report.out_of_bounds,
+            [ChartOutOfBounds {
+                chart: chart(1),
+                atlas_index: 0,
+                min: [12.0, 12.0],
+                max: [17.0, 14.0],
+            }]
+        );
+        assert!(layout.validate(32, 0).out_of_bounds.is_empty());
+    }
+}